@@ -0,0 +1,205 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/// bandwidth limits for rsync depending on the time of day and a deadline for the transfer
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct Bandwidth {
+    /// limit outside of the scheduled windows; unlimited if missing
+    pub limit: Option<BandwidthLimit>,
+    #[serde(default)]
+    pub schedule: Vec<BandwidthWindow>,
+    /// time of day at which a running transfer is stopped (e.g. "07:00")
+    pub deadline: Option<TimeOfDay>,
+}
+
+/// limit applying between two times of day; the window wraps around midnight if `to` is before `from`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BandwidthWindow {
+    pub from: TimeOfDay,
+    pub to: TimeOfDay,
+    pub limit: BandwidthLimit,
+}
+
+/// value for `rsync --bwlimit` (KiB/s or with a unit, e.g. "1M"); "0" means unlimited
+#[derive(Debug, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct BandwidthLimit(String);
+
+impl TryFrom<String> for BandwidthLimit {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let digits = value.trim_end_matches(['K', 'k', 'M', 'm', 'G', 'g']);
+
+        match !digits.is_empty()
+            && value.len() - digits.len() <= 1
+            && digits.chars().all(|c| c.is_ascii_digit())
+        {
+            true => Ok(BandwidthLimit(value)),
+            false => Err(format!("invalid bandwidth limit \"{}\"", value)),
+        }
+    }
+}
+
+impl BandwidthLimit {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// time of day in the format "HH:MM" or "HH:MM:SS"
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(pub NaiveTime);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M:%S"))
+            .map(TimeOfDay)
+            .map_err(|_| format!("invalid time of day \"{}\"", value))
+    }
+}
+
+impl JsonSchema for TimeOfDay {
+    fn schema_name() -> String {
+        "TimeOfDay".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl BandwidthWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        match self.from.0 <= self.to.0 {
+            true => self.from.0 <= time && time < self.to.0,
+            false => self.from.0 <= time || time < self.to.0,
+        }
+    }
+}
+
+/// limits of a single rsync invocation
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TransferLimits {
+    pub bwlimit: Option<String>,
+    /// minutes after which rsync stops the transfer (`--time-limit`)
+    pub time_limit: Option<i64>,
+    /// whether the time limit is caused by the deadline (rather than the end of a bandwidth window)
+    pub deadline: bool,
+}
+
+impl Bandwidth {
+    /// get the limits for a transfer starting at the given (local) time
+    ///
+    /// The transfer is stopped when the bandwidth window changes, so that it can be restarted with the new limit, or when the deadline is reached.
+    pub fn limits(&self, now: &NaiveDateTime) -> TransferLimits {
+        let time = now.time();
+        let bwlimit = self
+            .schedule
+            .iter()
+            .find(|w| w.contains(time))
+            .map(|w| &w.limit)
+            .or(self.limit.as_ref())
+            .map(|l| l.as_str().to_string());
+        let next_change = self
+            .schedule
+            .iter()
+            .flat_map(|w| [w.from, w.to])
+            .map(|t| until(time, t.0))
+            .min();
+        let deadline = self.deadline.map(|t| until(time, t.0));
+        let (stop, deadline) = match (next_change, deadline) {
+            (Some(change), Some(deadline)) if change < deadline => (Some(change), false),
+            (_, Some(deadline)) => (Some(deadline), true),
+            (change, None) => (change, false),
+        };
+
+        TransferLimits {
+            bwlimit,
+            time_limit: stop.map(|d| d.num_minutes().max(1)),
+            deadline,
+        }
+    }
+}
+
+/// time until the next occurrence of a time of day
+fn until(now: NaiveTime, time: NaiveTime) -> Duration {
+    let duration = time - now;
+
+    match duration <= Duration::zero() {
+        true => duration + Duration::days(1),
+        false => duration,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn bandwidth() -> Bandwidth {
+        serde_json::from_str(
+            r#"{
+                "limit": "10M",
+                "schedule": [
+                    {"from": "08:00", "to": "18:00", "limit": "1M"},
+                    {"from": "22:00", "to": "02:00", "limit": "0"}
+                ],
+                "deadline": "07:00"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2022, 11, 1).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn limits() {
+        let bandwidth = bandwidth();
+
+        assert_eq!(
+            bandwidth.limits(&at(9, 0)),
+            TransferLimits {
+                bwlimit: Some("1M".to_string()),
+                time_limit: Some(9 * 60),
+                deadline: false
+            }
+        );
+        assert_eq!(
+            bandwidth.limits(&at(23, 30)),
+            TransferLimits {
+                bwlimit: Some("0".to_string()),
+                time_limit: Some(150),
+                deadline: false
+            }
+        );
+        assert_eq!(
+            bandwidth.limits(&at(3, 0)),
+            TransferLimits {
+                bwlimit: Some("10M".to_string()),
+                time_limit: Some(4 * 60),
+                deadline: true
+            }
+        );
+        assert_eq!(
+            Bandwidth::default().limits(&at(3, 0)),
+            TransferLimits::default()
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(BandwidthLimit::try_from("1.5M".to_string()).is_err());
+        assert!(BandwidthLimit::try_from("M".to_string()).is_err());
+        assert!(BandwidthLimit::try_from("100kk".to_string()).is_err());
+        assert!(TimeOfDay::try_from("25:00".to_string()).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    commands, config::Config, custom_duration::CustomDuration, executor::Executor, list,
+    naming::NamingScheme, sync_error::SyncError,
+};
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, path::Path};
+
+/// thresholds of the `check` command
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
+#[serde(default)]
+pub struct CheckThresholds {
+    /// age of the newest snapshot above which the check warns
+    pub warning_age: CustomDuration,
+    /// age of the newest snapshot above which the check is critical
+    pub critical_age: CustomDuration,
+    /// number of snapshots below which the check is critical
+    pub min_snapshots: usize,
+}
+
+impl Default for CheckThresholds {
+    fn default() -> Self {
+        Self {
+            warning_age: CustomDuration::days(2),
+            critical_age: CustomDuration::days(4),
+            min_snapshots: 1,
+        }
+    }
+}
+
+/// status of a check; the exit codes follow the conventions of Nagios plugins
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl CheckStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "OK",
+            CheckStatus::Warning => "WARNING",
+            CheckStatus::Critical => "CRITICAL",
+            CheckStatus::Unknown => "UNKNOWN",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CheckStatus::Ok => 0,
+            CheckStatus::Warning => 1,
+            CheckStatus::Critical => 2,
+            CheckStatus::Unknown => 3,
+        }
+    }
+}
+
+/// result of the staleness check of a job
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub message: String,
+    pub newest_snapshot: Option<String>,
+    pub age_seconds: Option<i64>,
+    pub snapshot_count: usize,
+    pub warning_seconds: i64,
+    pub critical_seconds: i64,
+    pub min_snapshots: usize,
+}
+
+impl CheckResult {
+    /// result of a check that could not be completed (e.g. the server is unreachable)
+    pub fn unknown(message: &str) -> Self {
+        Self {
+            status: CheckStatus::Unknown,
+            message: message.to_string(),
+            newest_snapshot: None,
+            age_seconds: None,
+            snapshot_count: 0,
+            warning_seconds: 0,
+            critical_seconds: 0,
+            min_snapshots: 0,
+        }
+    }
+
+    /// format the result as a line of plugin output with performance data
+    pub fn format_text(&self) -> String {
+        let mut text = format!("BACKUP {} - {}", self.status.name(), self.message);
+
+        if self.status != CheckStatus::Unknown {
+            text.push_str(&format!(
+                " | snapshots={};;{}:",
+                self.snapshot_count, self.min_snapshots
+            ));
+
+            if let Some(age) = self.age_seconds {
+                text.push_str(&format!(
+                    " age={}s;{};{}",
+                    age, self.warning_seconds, self.critical_seconds
+                ));
+            }
+        }
+
+        text.push('\n');
+        text
+    }
+}
+
+/// check the age of the newest snapshot and the number of snapshots of the job on the server
+pub fn check(
+    exec: &dyn Executor,
+    config: &Config,
+    date_time: &DateTime<Utc>,
+) -> Result<CheckResult, SyncError> {
+    let snapshots = commands::get_snapshots(
+        exec,
+        config.server(),
+        Path::new(&config.snapshot),
+        &NamingScheme::from_config(config)?,
+    )?;
+
+    evaluate(&config.check, &snapshots, date_time)
+}
+
+/// evaluate the snapshots against the thresholds
+pub fn evaluate(
+    thresholds: &CheckThresholds,
+    snapshots: &[(DateTime<Utc>, String)],
+    date_time: &DateTime<Utc>,
+) -> Result<CheckResult, SyncError> {
+    let warning = Duration::try_from(&thresholds.warning_age)?;
+    let critical = Duration::try_from(&thresholds.critical_age)?;
+    let newest = snapshots.iter().max_by_key(|(date, _)| *date);
+    let age = newest.map(|(date, _)| *date_time - *date);
+    let (status, message) = match (newest, age) {
+        (Some((_, name)), Some(age)) => {
+            let status = if age > critical {
+                CheckStatus::Critical
+            } else if age > warning {
+                CheckStatus::Warning
+            } else {
+                CheckStatus::Ok
+            };
+
+            (
+                status,
+                format!("newest snapshot {} is {} old", name, list::format_age(age)),
+            )
+        }
+        _ => (CheckStatus::Critical, "no snapshot found".to_string()),
+    };
+    let (status, message) = match snapshots.len() < thresholds.min_snapshots {
+        true => (
+            CheckStatus::Critical,
+            format!(
+                "{}; {} snapshot(s), expected at least {}",
+                message,
+                snapshots.len(),
+                thresholds.min_snapshots
+            ),
+        ),
+        false => (
+            status,
+            format!("{}; {} snapshot(s)", message, snapshots.len()),
+        ),
+    };
+
+    Ok(CheckResult {
+        status,
+        message,
+        newest_snapshot: newest.map(|(_, name)| name.clone()),
+        age_seconds: age.map(|a| a.num_seconds()),
+        snapshot_count: snapshots.len(),
+        warning_seconds: warning.num_seconds(),
+        critical_seconds: critical.num_seconds(),
+        min_snapshots: thresholds.min_snapshots,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+    use chrono::TimeZone;
+
+    fn snapshots() -> Vec<(DateTime<Utc>, String)> {
+        vec![
+            (
+                Utc.ymd(2022, 11, 1).and_hms(12, 0, 0),
+                "2022-11-01T12:00:00Z_test".to_string(),
+            ),
+            (
+                Utc.ymd(2022, 11, 2).and_hms(12, 0, 0),
+                "2022-11-02T12:00:00Z_test".to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn evaluate() {
+        let thresholds = CheckThresholds::default();
+        let status = |date_time: DateTime<Utc>| {
+            super::evaluate(&thresholds, &snapshots(), &date_time)
+                .unwrap()
+                .status
+        };
+
+        assert_eq!(
+            status(Utc.ymd(2022, 11, 3).and_hms(12, 0, 0)),
+            CheckStatus::Ok
+        );
+        assert_eq!(
+            status(Utc.ymd(2022, 11, 5).and_hms(12, 0, 0)),
+            CheckStatus::Warning
+        );
+        assert_eq!(
+            status(Utc.ymd(2022, 11, 6).and_hms(13, 0, 0)),
+            CheckStatus::Critical
+        );
+
+        let res = super::evaluate(
+            &CheckThresholds {
+                min_snapshots: 3,
+                ..Default::default()
+            },
+            &snapshots(),
+            &Utc.ymd(2022, 11, 2).and_hms(13, 0, 0),
+        )
+        .unwrap();
+
+        assert_eq!(res.status, CheckStatus::Critical);
+        assert_eq!(
+            res.format_text(),
+            "BACKUP CRITICAL - newest snapshot 2022-11-02T12:00:00Z_test is 1h 0m old; \
+             2 snapshot(s), expected at least 3 | snapshots=2;;3: age=3600s;172800;345600\n"
+        );
+
+        let res =
+            super::evaluate(&thresholds, &[], &Utc.ymd(2022, 11, 2).and_hms(13, 0, 0)).unwrap();
+
+        assert_eq!(res.status, CheckStatus::Critical);
+        assert_eq!(
+            res.format_text(),
+            "BACKUP CRITICAL - no snapshot found; 0 snapshot(s), expected at least 1 \
+             | snapshots=0;;1:\n"
+        );
+    }
+
+    #[test]
+    fn check() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..], ["ls", "-A1", "snapshot"]);
+            Ok("2022-11-01T12:00:00Z_test\n2022-11-02T12:00:00Z_other\n".to_string())
+        });
+
+        let config = Config {
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test".to_string(),
+            ..Default::default()
+        };
+        let res = super::check(&mock, &config, &Utc.ymd(2022, 11, 3).and_hms(11, 0, 0)).unwrap();
+
+        assert_eq!(res.status, CheckStatus::Ok);
+        assert_eq!(
+            res.newest_snapshot,
+            Some("2022-11-01T12:00:00Z_test".to_string())
+        );
+        assert_eq!(res.age_seconds, Some(47 * 3600));
+    }
+}
@@ -0,0 +1,487 @@
+use crate::clock::SnapshotTimeZone;
+use anyhow::{anyhow, bail, Context, Result};
+
+pub const USAGE: &str = "usage: backup-rsync [command] [options]
+
+The configuration file is read from the path in BACK_UP_RSYNC_CONFIG.
+
+commands:
+  run                    sync, create a snapshot and prune old snapshots (default)
+  list [--json] [--usage] [--time-zone <tz>] [--unrecognized]
+                         list the snapshots with their age and retention status;
+                         --usage computes the apparent and unique disk usage,
+                         --time-zone shows the dates in the time zone (utc,
+                         local or an offset like +01:00),
+                         --unrecognized lists the entries of the snapshot directory
+                         not matching the naming scheme instead
+  diff <old> [<new>] [--path <prefix>]... [--manifest] [--json]
+                         show the files added, removed and modified between two
+                         snapshots or between a snapshot and the source;
+                         --manifest compares the manifests of the snapshots
+  verify [--sample <count> | --manifest] [--json]
+                         compare the source with the latest snapshot using checksums;
+                         --sample only compares a random sample of files,
+                         --manifest checks the snapshot against its manifest
+  excluded [--json]      list the files and directories below the source excluded by
+                         the include and exclude rules
+  migrate-names [--from <template>] [--dry-run]
+                         rename the snapshots named according to an old template
+                         (default {date}_{suffix}) to the configured naming scheme
+  daemon [<config>...]   run the jobs of the configuration files (default: the file in
+                         BACK_UP_RSYNC_CONFIG) according to their schedules
+  systemd [--user] [--output <dir>]
+                         generate a systemd service and timer for the job; --user
+                         generates units for the service manager of the user,
+                         --output writes them to the directory instead of printing them
+  status [--json]        show the outcome of the last run (requires state_dir)
+  history [--job <name>] [--limit <count>] [--json]
+                         list the recorded runs of the job (requires state_dir);
+                         --job selects another job sharing the state directory,
+                         --limit only lists the most recent runs
+  last [--job <name>] [--success] [--json]
+                         show the last run of the job; --success only considers
+                         successful runs
+  check [--json]         check the age of the newest snapshot and the number of
+                         snapshots on the server; exits with 0 (ok), 1 (warning),
+                         2 (critical) or 3 (unknown) like a Nagios plugin
+  schema                 print the JSON schema of the configuration file
+  migrate-config [<config>...] [--dry-run]
+                         upgrade the configuration files (default: the file in
+                         BACK_UP_RSYNC_CONFIG) to the current format; the original
+                         files are kept with the extension .bak
+  help                   show this message
+";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    List {
+        json: bool,
+        usage: bool,
+        time_zone: Option<SnapshotTimeZone>,
+        unrecognized: bool,
+    },
+    Diff {
+        old: String,
+        new: Option<String>,
+        paths: Vec<String>,
+        manifest: bool,
+        json: bool,
+    },
+    Verify {
+        sample: Option<usize>,
+        manifest: bool,
+        json: bool,
+    },
+    Excluded {
+        json: bool,
+    },
+    MigrateNames {
+        from: Option<String>,
+        dry_run: bool,
+    },
+    Daemon {
+        configs: Vec<String>,
+    },
+    Systemd {
+        user: bool,
+        output: Option<String>,
+    },
+    Status {
+        json: bool,
+    },
+    History {
+        job: Option<String>,
+        limit: Option<usize>,
+        json: bool,
+    },
+    Last {
+        job: Option<String>,
+        success: bool,
+        json: bool,
+    },
+    Check {
+        json: bool,
+    },
+    Schema,
+    MigrateConfig {
+        configs: Vec<String>,
+        dry_run: bool,
+    },
+    Help,
+}
+
+/// parse the command line arguments (without the program name)
+pub fn parse(args: &[String]) -> Result<Command> {
+    let (command, options) = match args.split_first() {
+        Some((command, options)) => (command.as_str(), options),
+        None => return Ok(Command::Run),
+    };
+    let mut options = Options::new(options);
+    let res = match command {
+        "run" => Command::Run,
+        "list" => Command::List {
+            json: options.flag("--json"),
+            usage: options.flag("--usage"),
+            time_zone: options
+                .value("--time-zone")?
+                .map(SnapshotTimeZone::try_from)
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            unrecognized: options.flag("--unrecognized"),
+        },
+        "diff" => {
+            let paths = options.values("--path")?;
+            let manifest = options.flag("--manifest");
+            let json = options.flag("--json");
+            let old = options.required_positional("old snapshot")?;
+            let new = options.positional();
+
+            if manifest && new.is_none() {
+                bail!("--manifest requires two snapshots");
+            }
+
+            Command::Diff {
+                old,
+                new,
+                paths,
+                manifest,
+                json,
+            }
+        }
+        "verify" => {
+            let sample = options
+                .value("--sample")?
+                .map(|s| s.parse())
+                .transpose()
+                .context("invalid sample size")?;
+            let manifest = options.flag("--manifest");
+
+            if manifest && sample.is_some() {
+                bail!("--sample and --manifest cannot be combined");
+            }
+
+            Command::Verify {
+                sample,
+                manifest,
+                json: options.flag("--json"),
+            }
+        }
+        "excluded" => Command::Excluded {
+            json: options.flag("--json"),
+        },
+        "migrate-names" => Command::MigrateNames {
+            from: options.value("--from")?,
+            dry_run: options.flag("--dry-run"),
+        },
+        "daemon" => Command::Daemon {
+            configs: std::iter::from_fn(|| options.positional()).collect(),
+        },
+        "systemd" => Command::Systemd {
+            user: options.flag("--user"),
+            output: options.value("--output")?,
+        },
+        "status" => Command::Status {
+            json: options.flag("--json"),
+        },
+        "history" => Command::History {
+            job: options.value("--job")?,
+            limit: options
+                .value("--limit")?
+                .map(|l| l.parse())
+                .transpose()
+                .context("invalid limit")?,
+            json: options.flag("--json"),
+        },
+        "last" => Command::Last {
+            job: options.value("--job")?,
+            success: options.flag("--success"),
+            json: options.flag("--json"),
+        },
+        "check" => Command::Check {
+            json: options.flag("--json"),
+        },
+        "schema" => Command::Schema,
+        "migrate-config" => Command::MigrateConfig {
+            dry_run: options.flag("--dry-run"),
+            configs: std::iter::from_fn(|| options.positional()).collect(),
+        },
+        "help" | "--help" | "-h" => Command::Help,
+        _ => bail!("unknown command \"{}\"\n\n{}", command, USAGE),
+    };
+
+    options.finish()?;
+
+    Ok(res)
+}
+
+/// options of a command; every option must be consumed exactly once
+struct Options<'a> {
+    args: Vec<Option<&'a str>>,
+}
+
+impl<'a> Options<'a> {
+    fn new(args: &'a [String]) -> Self {
+        Self {
+            args: args.iter().map(|a| Some(a.as_str())).collect(),
+        }
+    }
+
+    /// check whether a flag is present
+    fn flag(&mut self, name: &str) -> bool {
+        match self.args.iter().position(|a| *a == Some(name)) {
+            Some(idx) => {
+                self.args[idx] = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// get all values of an option that can be given multiple times (e.g. "--path a --path b")
+    fn values(&mut self, name: &str) -> Result<Vec<String>> {
+        let mut values = Vec::new();
+
+        while let Some(idx) = self.args.iter().position(|a| *a == Some(name)) {
+            match self.args.get(idx + 1).copied().flatten() {
+                Some(value) => values.push(value.to_string()),
+                None => bail!("missing value for option \"{}\"", name),
+            }
+
+            self.args[idx] = None;
+            self.args[idx + 1] = None;
+        }
+
+        Ok(values)
+    }
+
+    /// get the value of an option that can be given once
+    fn value(&mut self, name: &str) -> Result<Option<String>> {
+        let mut values = self.values(name)?;
+
+        if values.len() > 1 {
+            bail!("option \"{}\" can only be given once", name);
+        }
+
+        Ok(values.pop())
+    }
+
+    /// get the first remaining argument that is not an option
+    fn positional(&mut self) -> Option<String> {
+        let idx = self
+            .args
+            .iter()
+            .position(|a| matches!(a, Some(a) if !a.starts_with("--")))?;
+
+        self.args[idx].take().map(|a| a.to_string())
+    }
+
+    fn required_positional(&mut self, name: &str) -> Result<String> {
+        match self.positional() {
+            Some(value) => Ok(value),
+            None => bail!("missing argument \"{}\"\n\n{}", name, USAGE),
+        }
+    }
+
+    /// fail if there are unknown options left
+    fn finish(self) -> Result<()> {
+        let unknown = self.args.into_iter().flatten().collect::<Vec<&str>>();
+
+        if !unknown.is_empty() {
+            bail!("unknown option(s) \"{}\"\n\n{}", unknown.join(" "), USAGE);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_run() {
+        assert_eq!(parse(&args(&[])).unwrap(), Command::Run);
+        assert_eq!(parse(&args(&["run"])).unwrap(), Command::Run);
+    }
+
+    #[test]
+    fn parse_list() {
+        assert_eq!(
+            parse(&args(&["list", "--usage"])).unwrap(),
+            Command::List {
+                json: false,
+                usage: true,
+                time_zone: None,
+                unrecognized: false
+            }
+        );
+        assert_eq!(
+            parse(&args(&["list", "--time-zone", "+01:00"])).unwrap(),
+            Command::List {
+                json: false,
+                usage: false,
+                time_zone: Some(SnapshotTimeZone::Fixed(chrono::FixedOffset::east(3600))),
+                unrecognized: false
+            }
+        );
+        assert!(parse(&args(&["list", "--time-zone", "Mars"])).is_err());
+        assert!(parse(&args(&["list", "--size"])).is_err());
+    }
+
+    #[test]
+    fn parse_diff() {
+        assert_eq!(
+            parse(&args(&[
+                "diff", "a", "--path", "home/", "b", "--path", "etc/"
+            ]))
+            .unwrap(),
+            Command::Diff {
+                old: "a".to_string(),
+                new: Some("b".to_string()),
+                paths: vec!["home/".to_string(), "etc/".to_string()],
+                manifest: false,
+                json: false,
+            }
+        );
+        assert_eq!(
+            parse(&args(&["diff", "a", "--json"])).unwrap(),
+            Command::Diff {
+                old: "a".to_string(),
+                new: None,
+                paths: vec![],
+                manifest: false,
+                json: true,
+            }
+        );
+        assert!(parse(&args(&["diff"])).is_err());
+        assert!(parse(&args(&["diff", "a", "--manifest"])).is_err());
+        assert!(parse(&args(&["diff", "a", "--path"])).is_err());
+    }
+
+    #[test]
+    fn parse_verify() {
+        assert_eq!(
+            parse(&args(&["verify", "--sample", "100"])).unwrap(),
+            Command::Verify {
+                sample: Some(100),
+                manifest: false,
+                json: false
+            }
+        );
+        assert!(parse(&args(&["verify", "--sample", "1", "--manifest"])).is_err());
+        assert!(parse(&args(&["verify", "--sample", "many"])).is_err());
+    }
+
+    #[test]
+    fn parse_excluded() {
+        assert_eq!(
+            parse(&args(&["excluded", "--json"])).unwrap(),
+            Command::Excluded { json: true }
+        );
+        assert!(parse(&args(&["excluded", "/home"])).is_err());
+    }
+
+    #[test]
+    fn parse_migrate_names() {
+        assert_eq!(
+            parse(&args(&["migrate-names", "--dry-run"])).unwrap(),
+            Command::MigrateNames {
+                from: None,
+                dry_run: true
+            }
+        );
+        assert_eq!(
+            parse(&args(&["migrate-names", "--from", "{date}-{suffix}"])).unwrap(),
+            Command::MigrateNames {
+                from: Some("{date}-{suffix}".to_string()),
+                dry_run: false
+            }
+        );
+        assert!(parse(&args(&["migrate-names", "--from"])).is_err());
+    }
+
+    #[test]
+    fn parse_daemon() {
+        assert_eq!(
+            parse(&args(&["daemon"])).unwrap(),
+            Command::Daemon {
+                configs: Vec::new()
+            }
+        );
+        assert_eq!(
+            parse(&args(&["daemon", "home.json", "etc.json"])).unwrap(),
+            Command::Daemon {
+                configs: vec!["home.json".to_string(), "etc.json".to_string()]
+            }
+        );
+        assert!(parse(&args(&["daemon", "--once"])).is_err());
+    }
+
+    #[test]
+    fn parse_systemd() {
+        assert_eq!(
+            parse(&args(&["systemd", "--user", "--output", "units"])).unwrap(),
+            Command::Systemd {
+                user: true,
+                output: Some("units".to_string())
+            }
+        );
+        assert_eq!(
+            parse(&args(&["status"])).unwrap(),
+            Command::Status { json: false }
+        );
+    }
+
+    #[test]
+    fn parse_history() {
+        assert_eq!(
+            parse(&args(&["history", "--limit", "10", "--job", "laptop"])).unwrap(),
+            Command::History {
+                job: Some("laptop".to_string()),
+                limit: Some(10),
+                json: false
+            }
+        );
+        assert_eq!(
+            parse(&args(&["last", "--success", "--json"])).unwrap(),
+            Command::Last {
+                job: None,
+                success: true,
+                json: true
+            }
+        );
+        assert!(parse(&args(&["history", "--limit", "ten"])).is_err());
+    }
+
+    #[test]
+    fn parse_check() {
+        assert_eq!(
+            parse(&args(&["check", "--json"])).unwrap(),
+            Command::Check { json: true }
+        );
+        assert!(parse(&args(&["check", "--warning", "1d"])).is_err());
+    }
+
+    #[test]
+    fn parse_migrate_config() {
+        assert_eq!(
+            parse(&args(&["migrate-config", "home.json", "--dry-run"])).unwrap(),
+            Command::MigrateConfig {
+                configs: vec!["home.json".to_string()],
+                dry_run: true
+            }
+        );
+        assert_eq!(parse(&args(&["schema"])).unwrap(), Command::Schema);
+        assert!(parse(&args(&["schema", "--json"])).is_err());
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert!(parse(&args(&["lsit"])).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{commands, executor::Executor, ssh_credentials::SshCredentials, sync_error::SyncError};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Deserialize;
+use std::{convert::TryFrom, fmt};
+
+/// what to do if the local clock deviates too much from the clock of the server
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSkewPolicy {
+    #[default]
+    Warn,
+    Abort,
+}
+
+/// time zone used for the dates in snapshot names and listings: "utc", "local" or a fixed offset (e.g. "+01:00")
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum SnapshotTimeZone {
+    #[default]
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl TryFrom<String> for SnapshotTimeZone {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time zone \"{}\"", value);
+
+        match value.to_lowercase().as_str() {
+            "utc" | "z" => Ok(SnapshotTimeZone::Utc),
+            "local" => Ok(SnapshotTimeZone::Local),
+            offset => {
+                let (sign, offset) = match offset.split_at(offset.len().min(1)) {
+                    ("+", offset) => (1, offset),
+                    ("-", offset) => (-1, offset),
+                    _ => return Err(invalid()),
+                };
+                let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+                let hours = hours.parse::<i32>().map_err(|_| invalid())?;
+                let minutes = minutes.parse::<i32>().map_err(|_| invalid())?;
+
+                if minutes >= 60 {
+                    return Err(invalid());
+                }
+
+                FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+                    .map(SnapshotTimeZone::Fixed)
+                    .ok_or_else(invalid)
+            }
+        }
+    }
+}
+
+impl JsonSchema for SnapshotTimeZone {
+    fn schema_name() -> String {
+        "SnapshotTimeZone".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl fmt::Display for SnapshotTimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotTimeZone::Utc => write!(f, "utc"),
+            SnapshotTimeZone::Local => write!(f, "local"),
+            SnapshotTimeZone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl SnapshotTimeZone {
+    /// convert a time to the time zone
+    pub fn convert(&self, date_time: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            SnapshotTimeZone::Utc => date_time.with_timezone(&FixedOffset::east(0)),
+            SnapshotTimeZone::Local => {
+                let local = date_time.with_timezone(&Local);
+
+                local.with_timezone(local.offset())
+            }
+            SnapshotTimeZone::Fixed(offset) => date_time.with_timezone(offset),
+        }
+    }
+
+    /// interpret a time without offset in the time zone
+    pub fn from_local(&self, date_time: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            SnapshotTimeZone::Utc => Some(DateTime::from_utc(*date_time, Utc)),
+            SnapshotTimeZone::Local => Local
+                .from_local_datetime(date_time)
+                .earliest()
+                .map(|d| d.into()),
+            SnapshotTimeZone::Fixed(offset) => offset
+                .from_local_datetime(date_time)
+                .earliest()
+                .map(|d| d.into()),
+        }
+    }
+}
+
+/// get the difference between the local clock and the clock of the server (positive if the local clock is ahead)
+pub fn clock_skew(exec: &dyn Executor, ssh_creds: &SshCredentials) -> Result<Duration, SyncError> {
+    let before = Utc::now();
+    let output = commands::remote_exec(exec, ssh_creds, &["date", "-u", "+%s"])?;
+    let after = Utc::now();
+    let remote = output.trim().parse::<i64>()?;
+    // assume the remote clock was read halfway through the round trip
+    let local = before + (after - before) / 2;
+
+    Ok(Duration::seconds(local.timestamp() - remote))
+}
+
+/// compare the local clock with the clock of the server and warn or fail if the difference exceeds the maximum
+pub fn check_clock_skew(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    max_skew: Duration,
+    policy: ClockSkewPolicy,
+) -> Result<Duration, SyncError> {
+    let skew = clock_skew(exec, ssh_creds)?;
+
+    if skew.num_seconds().abs() > max_skew.num_seconds() {
+        match policy {
+            ClockSkewPolicy::Warn => log::warn!(
+                "local clock differs from the server clock by {}s",
+                skew.num_seconds()
+            ),
+            ClockSkewPolicy::Abort => return Err(SyncError::ClockSkew(skew.num_seconds())),
+        }
+    }
+
+    Ok(skew)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_zone() {
+        assert_eq!(
+            SnapshotTimeZone::try_from("UTC".to_string()),
+            Ok(SnapshotTimeZone::Utc)
+        );
+        assert_eq!(
+            SnapshotTimeZone::try_from("local".to_string()),
+            Ok(SnapshotTimeZone::Local)
+        );
+        assert_eq!(
+            SnapshotTimeZone::try_from("-05:30".to_string()),
+            Ok(SnapshotTimeZone::Fixed(FixedOffset::west(5 * 3600 + 1800)))
+        );
+        assert_eq!(
+            SnapshotTimeZone::try_from("+2".to_string()),
+            Ok(SnapshotTimeZone::Fixed(FixedOffset::east(2 * 3600)))
+        );
+        assert!(SnapshotTimeZone::try_from("Europe/Berlin".to_string()).is_err());
+        assert!(SnapshotTimeZone::try_from("+01:60".to_string()).is_err());
+
+        let tz = SnapshotTimeZone::Fixed(FixedOffset::east(3600));
+        let date_time = Utc.ymd(2022, 11, 1).and_hms(12, 0, 0);
+
+        assert_eq!(
+            tz.convert(&date_time).to_rfc3339(),
+            "2022-11-01T13:00:00+01:00"
+        );
+        assert_eq!(
+            tz.from_local(&date_time.naive_utc()),
+            Some(Utc.ymd(2022, 11, 1).and_hms(11, 0, 0))
+        );
+    }
+
+    #[test]
+    fn check_clock_skew() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().times(2).returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(args[5..], ["date", "-u", "+%s"]);
+            Ok(format!("{}\n", Utc::now().timestamp() - 600))
+        });
+
+        let skew = super::check_clock_skew(
+            &mock,
+            &SshCredentials::test("user", "id_file", "host"),
+            Duration::minutes(15),
+            ClockSkewPolicy::Abort,
+        )
+        .unwrap();
+
+        assert!((599..=601).contains(&skew.num_seconds()));
+        assert!(super::check_clock_skew(
+            &mock,
+            &SshCredentials::test("user", "id_file", "host"),
+            Duration::minutes(5),
+            ClockSkewPolicy::Abort,
+        )
+        .is_err());
+    }
+}
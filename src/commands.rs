@@ -1,129 +1,219 @@
-use std::path::Path;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
-use chrono::{DateTime, Utc};
-use exec_rs::Exec;
+use chrono::{DateTime, Duration, Utc};
 
-use crate::{ssh_credentials::SshCredentials, sync_error::SyncError};
+use crate::{
+    bandwidth::TransferLimits,
+    config::SyncMode,
+    executor::{ExecOptions, Executor},
+    filter::FilterRules,
+    hooks::{Hook, HookContext, HookFailurePolicy, HookStage},
+    manifest,
+    naming::NamingScheme,
+    progress::TransferProgress,
+    rsync_options::RsyncOptions,
+    source::Source,
+    ssh_credentials::SshCredentials,
+    sync_error::SyncError,
+};
 
-/// run rsync to synchronize the local files with the files on the server
+/// exit status of rsync if files vanished during the transfer; the transfer is complete otherwise
+pub const RSYNC_VANISHED: i32 = 24;
+/// exit status of rsync when it stopped at the time limit (`--time-limit`)
+pub const RSYNC_TIME_LIMIT: i32 = 30;
+
+/// run rsync to synchronize the files of the client with the files on the server; returns the output of rsync including the transfer statistics and its exit status (0 or `RSYNC_VANISHED`, or `RSYNC_TIME_LIMIT` if a time limit is set)
+///
+/// In push mode, the source is local and the destination is on the server reached via ssh. In pull mode, the source is on the client reached via ssh and the destination is local.
+#[allow(clippy::too_many_arguments)]
 pub fn sync_backup(
-    exec: &dyn Exec,
+    exec: &dyn Executor,
     ssh_creds: &SshCredentials,
-    exclude_file: &Path,
-    source: &Path,
+    mode: SyncMode,
+    options: &RsyncOptions,
+    filter: &FilterRules,
+    source: &Source,
     destination: &Path,
     log_file: &Path,
-) -> Result<String, SyncError> {
-    // rsync -ave "ssh -l ${conf.sshUser} -i ${conf.sshIdFilename}" --compress --one-file-system --exclude-from=${conf.excludeFilename} --delete-after --delete-excluded ${conf.source} ${conf.destination} > ${conf.logFilename}
-    let ssh_command = vec!["ssh", "-l", &ssh_creds.user, "-i", &ssh_creds.id_file].join(" ");
-    let exclude_file = format!(
-        "--exclude-from={}",
-        exclude_file
+    limits: &TransferLimits,
+    mut on_progress: Option<&mut dyn FnMut(&TransferProgress)>,
+) -> Result<(String, i32), SyncError> {
+    // rsync -ave "ssh -l ${conf.sshUser} -i ${conf.sshIdFilename}" --compress --one-file-system --exclude-from=${conf.excludeFilename} --delete-after --delete-excluded --stats --log-file=${conf.logFilename} ${conf.source} ${conf.destination}
+    let ssh_command = ssh_creds.ssh_command();
+    let filter_args = filter.args()?;
+    let paths = transfer_paths(ssh_creds, mode, source, destination)?;
+    let log_file = format!(
+        "--log-file={}",
+        log_file
             .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("exclude file".to_string()))?
+            .ok_or_else(|| SyncError::PathConversionError("log file".to_string()))?
     );
-    let destination = format!(
-        "{}@{}:{}",
-        ssh_creds.user,
-        ssh_creds.host,
-        destination
-            .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("destination".to_string()))?
+    let bwlimit = limits.bwlimit.as_ref().map(|l| format!("--bwlimit={}", l));
+    let time_limit = limits.time_limit.map(|l| format!("--time-limit={}", l));
+    let transfer_args = options.transfer_args();
+    let file_args = options.file_args();
+    let mut rsync_args = vec!["-ave", &ssh_command];
+
+    rsync_args.extend(transfer_args.iter().map(|a| a.as_str()));
+    rsync_args.extend(filter_args.iter().map(|a| a.as_str()));
+    rsync_args.extend(
+        file_args
+            .iter()
+            .chain(bwlimit.iter())
+            .chain(time_limit.iter())
+            .map(|a| a.as_str()),
     );
-    let rsync_args = vec![
-        "-ave",
-        &ssh_command,
-        "--compress",
-        "--one-file-system",
-        &exclude_file,
-        "--delete-after",
-        "--delete-excluded",
-        source
-            .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("source".to_string()))?,
-        &destination,
-        ">",
-        log_file
-            .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("log file".to_string()))?,
-    ];
+    rsync_args.extend(["--stats", &log_file]);
+
+    if on_progress.is_some() {
+        rsync_args.push("--info=progress2");
+    }
+
+    rsync_args.extend(paths.iter().map(|a| a.as_str()));
 
-    let res = exec.exec("rsync", &rsync_args[..])?;
+    let mut on_stdout = |line: &str| match (TransferProgress::parse(line), on_progress.as_mut()) {
+        (Some(progress), Some(on_progress)) => on_progress(&progress),
+        _ => log::debug!("{}", line),
+    };
+    let mut exit_status = 0;
+    let allowed_statuses = match limits.time_limit {
+        Some(_) => &[RSYNC_VANISHED, RSYNC_TIME_LIMIT][..],
+        None => &[RSYNC_VANISHED][..],
+    };
+    // errors are logged as they occur, as the transfer may take hours
+    let res = exec.exec_with(
+        "rsync",
+        &rsync_args[..],
+        ExecOptions {
+            on_stdout: Some(&mut on_stdout),
+            on_stderr: Some(&mut |line| log::warn!("{}", line)),
+            allowed_statuses,
+            exit_status: Some(&mut exit_status),
+            ..Default::default()
+        },
+    )?;
+
+    if exit_status == RSYNC_VANISHED {
+        log::warn!("some files vanished during the transfer");
+    }
 
-    Ok(res)
+    Ok((res, exit_status))
 }
 
 /// create a snapshot using a hard link from the backup directory to a timestamped directory in the snapshot folder
+///
+/// Fails if the snapshot already exists (e.g. a second run within the resolution of the snapshot name template), as `cp` would copy the backup into the existing snapshot. The backup is copied to `incomplete_snapshot_path` first and moved into place when the copy is complete, so that an interrupted or failed copy never leaves a partial snapshot under the final name.
 pub fn create_snapshot(
-    exec: &dyn Exec,
-    ssh_creds: &SshCredentials,
+    exec: &dyn Executor,
+    server: Server,
     backup_path: &Path,
     snapshot_path: &Path,
 ) -> Result<String, SyncError> {
-    // cp -al "$bckPath" "$bckPath1"
-    let command = "ssh";
-    let args = [
-        "-l",
-        &ssh_creds.user,
-        "-i",
-        &ssh_creds.id_file,
-        &ssh_creds.host,
-        "cp",
-        "-al",
-        backup_path
-            .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("backup".to_string()))?,
-        snapshot_path
-            .to_str()
-            .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?,
-    ];
-    let res = exec.exec(command, &args)?;
+    let backup_path = backup_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("backup".to_string()))?;
+    let incomplete_path = incomplete_snapshot_path(snapshot_path);
+    let incomplete_path = incomplete_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?;
+    let snapshot_path = snapshot_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?;
+    // cp -al "$bckPath" "$bckPath1"; a leftover of an earlier failed copy is replaced
+    let script = format!(
+        "if [ -e {1} ]; then echo {3}; else rm -rf {2} && cp -al {0} {2} && mv -T {2} {1}; fi",
+        shell_quote(backup_path),
+        shell_quote(snapshot_path),
+        shell_quote(incomplete_path),
+        SNAPSHOT_EXISTS
+    );
+    let output = server_exec(exec, server, &["sh", "-c", &script])?;
 
-    Ok(res)
+    if output.trim() == SNAPSHOT_EXISTS {
+        return Err(SyncError::SnapshotExists(snapshot_path.to_string()));
+    }
+
+    Ok(output)
 }
 
-/// get snapshots
+/// output of the snapshot creation if the snapshot already exists
+const SNAPSHOT_EXISTS: &str = "exists";
+
+/// hidden sibling of the snapshot the backup is copied to before the snapshot is moved into place
+pub fn incomplete_snapshot_path(snapshot_path: &Path) -> PathBuf {
+    let name = snapshot_path.file_name().unwrap_or_default();
+
+    snapshot_path.with_file_name(format!(".{}.incomplete", name.to_string_lossy()))
+}
+
+/// get the entries of the snapshot directory up to the given depth (paths relative to the snapshot directory)
+pub fn get_snapshot_entries(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+    depth: usize,
+) -> Result<Vec<String>, SyncError> {
+    let snapshot_path = snapshot_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?;
+    let output = match depth {
+        // ls -A1
+        1 => server_exec(exec, server, &["ls", "-A1", snapshot_path])?,
+        _ => {
+            let depth = depth.to_string();
+
+            server_exec(
+                exec,
+                server,
+                &[
+                    "find",
+                    snapshot_path,
+                    "-mindepth",
+                    &depth,
+                    "-maxdepth",
+                    &depth,
+                    "-printf",
+                    "%P\\n",
+                ],
+            )?
+        }
+    };
+
+    Ok(output
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect())
+}
+
+/// get the snapshots named according to the naming scheme; other entries of the snapshot directory are ignored
 pub fn get_snapshots(
-    exec: &dyn Exec,
-    ssh_creds: &SshCredentials,
+    exec: &dyn Executor,
+    server: Server,
     snapshot_path: &Path,
+    scheme: &NamingScheme,
 ) -> Result<Vec<(DateTime<Utc>, String)>, SyncError> {
-    // ls -A1
-    Ok(exec
-        .exec(
-            "ssh",
-            &[
-                "-l",
-                &ssh_creds.user,
-                "-i",
-                &ssh_creds.id_file,
-                &ssh_creds.host,
-                "ls",
-                "-A1",
-                snapshot_path
-                    .to_str()
-                    .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?,
-            ],
-        )?
-        .split('\n')
-        .filter_map(|s| {
-            match s
-                .split('_')
-                .next()
-                .ok_or(SyncError::SplitError)
-                .and_then(|token| DateTime::parse_from_rfc3339(token).map_err(|e| e.into()))
-                .map(|date| (date.into(), s))
-            {
-                Ok((date, s)) => Some((date, s.to_string())),
-                Err(_) => None,
-            }
-        })
-        .collect::<Vec<(DateTime<Utc>, String)>>())
+    let entries = get_snapshot_entries(exec, server, snapshot_path, scheme.depth())?;
+    let (snapshots, unrecognized) = scheme.split(entries);
+
+    if !unrecognized.is_empty() {
+        log::warn!(
+            "{} entries of the snapshot directory do not match the naming scheme",
+            unrecognized.len()
+        );
+        log::debug!("unrecognized entries: {}", unrecognized.join(", "));
+    }
+
+    Ok(snapshots)
 }
+
 /// review snapshots and remove the ones not complying to the policy
 pub fn delete_snapshot(
-    exec: &dyn Exec,
-    ssh_creds: &SshCredentials,
+    exec: &dyn Executor,
+    server: Server,
     snapshot_path: &Path,
 ) -> Result<(), SyncError> {
     let snapshot_path = snapshot_path
@@ -133,28 +223,456 @@ pub fn delete_snapshot(
     if ["/", ""].iter().any(|&s| s == snapshot_path) {
         return Err(SyncError::PathDeletionError(snapshot_path.to_string()));
     }
-    exec.exec(
-        "ssh",
+    server_exec(exec, server, &["rm", "-r", snapshot_path])?;
+
+    Ok(())
+}
+
+/// get the apparent size and the size of the files not hard linked to any other file for each path on the server
+pub fn get_disk_usage(
+    exec: &dyn Executor,
+    server: Server,
+    paths: &[&Path],
+) -> Result<Vec<(u64, u64)>, SyncError> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let paths = paths
+        .iter()
+        .map(|p| {
+            p.to_str()
+                .map(|p| shell_quote(p).to_string())
+                .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))
+        })
+        .collect::<Result<Vec<String>, SyncError>>()?;
+    let script = format!(
+        "for d in {}; do \
+         a=$(du -sl --apparent-size -B1 \"$d\" | cut -f1); \
+         u=$(find \"$d\" -type f -links 1 -printf '%s\\n' | awk '{{s+=$1}} END {{print s+0}}'); \
+         printf '%s\\t%s\\n' \"$a\" \"$u\"; \
+         done",
+        paths.join(" ")
+    );
+    let output = server_exec(exec, server, &["sh", "-c", &script])?;
+    let usage = output
+        .lines()
+        .map(|line| {
+            let (apparent, unique) = line.split_once('\t').ok_or(SyncError::SplitError)?;
+
+            Ok((apparent.trim().parse()?, unique.trim().parse()?))
+        })
+        .collect::<Result<Vec<(u64, u64)>, SyncError>>()?;
+
+    if usage.len() != paths.len() {
+        return Err(SyncError::UnexpectedOutput(output));
+    }
+
+    Ok(usage)
+}
+
+/// compare two snapshots on the server; returns the itemized changes needed to turn the old into the new snapshot
+pub fn compare_snapshots(
+    exec: &dyn Executor,
+    server: Server,
+    old_path: &Path,
+    new_path: &Path,
+) -> Result<String, SyncError> {
+    // rsync -ani --delete "$new/" "$old/"
+    let old_path = format!(
+        "{}/",
+        old_path
+            .to_str()
+            .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?
+    );
+    let new_path = format!(
+        "{}/",
+        new_path
+            .to_str()
+            .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?
+    );
+
+    server_exec(
+        exec,
+        server,
         &[
-            "-l",
-            &ssh_creds.user,
-            "-i",
-            &ssh_creds.id_file,
-            &ssh_creds.host,
-            "rm",
-            "-r",
-            snapshot_path,
+            "rsync",
+            "-ani",
+            "--delete",
+            &metadata_exclude(),
+            &new_path,
+            &old_path,
         ],
+    )
+}
+
+/// compare the source with a snapshot; returns the itemized changes needed to turn the snapshot into the source
+pub fn compare_source(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    mode: SyncMode,
+    filter: &FilterRules,
+    source: &Source,
+    snapshot_path: &Path,
+) -> Result<String, SyncError> {
+    let ssh_command = ssh_creds.ssh_command();
+    let filter_args = filter.args()?;
+    let paths = transfer_paths(ssh_creds, mode, source, snapshot_path)?;
+    let metadata_exclude = metadata_exclude();
+    let mut rsync_args = vec!["-ani", "-e", &ssh_command, "--delete", &metadata_exclude];
+
+    rsync_args.extend(filter_args.iter().map(|a| a.as_str()));
+    rsync_args.extend(paths.iter().map(|a| a.as_str()));
+
+    exec.exec("rsync", &rsync_args[..])
+}
+
+/// compare the source with a snapshot using checksums without transferring anything; returns the itemized differences
+///
+/// If `files_from` is given, only the files listed in it (separated by NUL characters) are compared.
+pub fn verify_snapshot(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    mode: SyncMode,
+    filter: &FilterRules,
+    source: &Source,
+    snapshot_path: &Path,
+    files_from: Option<&Path>,
+) -> Result<String, SyncError> {
+    // rsync -anci -e "ssh ..." ${filter} --delete ${source} ${user}@${host}:${snapshot}
+    let ssh_command = ssh_creds.ssh_command();
+    let filter_args = filter.args()?;
+    // deleted files cannot be detected when only a list of files is compared
+    let selection = match files_from {
+        Some(files_from) => format!(
+            "--files-from={}",
+            files_from
+                .to_str()
+                .ok_or_else(|| SyncError::PathConversionError("file list".to_string()))?
+        ),
+        None => "--delete".to_string(),
+    };
+    let paths = transfer_paths(ssh_creds, mode, source, snapshot_path)?;
+    let metadata_exclude = metadata_exclude();
+    let mut rsync_args = vec!["-anci", "-e", &ssh_command, &metadata_exclude];
+
+    rsync_args.extend(filter_args.iter().map(|a| a.as_str()));
+    rsync_args.push(&selection);
+
+    if files_from.is_some() {
+        rsync_args.push("--from0");
+    }
+
+    rsync_args.extend(paths.iter().map(|a| a.as_str()));
+
+    exec.exec("rsync", &rsync_args[..])
+}
+
+/// list the source with the filter rules; returns the debug output of rsync naming the excluded files and the rules excluding them
+pub fn list_excluded(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    mode: SyncMode,
+    filter: &FilterRules,
+    source: &Source,
+) -> Result<String, SyncError> {
+    let ssh_command = ssh_creds.ssh_command();
+    let filter_args = filter.args()?;
+    let mut rsync_args = vec!["-r", "--list-only", "--debug=FILTER1"];
+
+    if mode == SyncMode::Pull {
+        rsync_args.extend(["-e", &ssh_command]);
+    }
+    rsync_args.extend(filter_args.iter().map(|a| a.as_str()));
+
+    // the source is passed as in the transfer (see `transfer_paths`), so that anchored patterns match the same files
+    let paths = match mode {
+        SyncMode::Push => source.paths().into_iter().map(|p| p.to_string()).collect(),
+        SyncMode::Pull => source
+            .paths()
+            .into_iter()
+            .map(|p| remote_path(ssh_creds, p))
+            .collect::<Vec<String>>(),
+    };
+
+    rsync_args.extend(source.options());
+    rsync_args.extend(paths.iter().map(|a| a.as_str()));
+
+    exec.exec("rsync", &rsync_args[..])
+}
+
+/// rsync arguments naming the source and the destination; the source is remote in pull mode, the destination otherwise
+fn transfer_paths(
+    ssh_creds: &SshCredentials,
+    mode: SyncMode,
+    source: &Source,
+    destination: &Path,
+) -> Result<Vec<String>, SyncError> {
+    let destination = destination
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("destination".to_string()))?;
+    let mut args = source
+        .options()
+        .into_iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<String>>();
+
+    match mode {
+        SyncMode::Push => {
+            args.extend(source.paths().into_iter().map(|p| p.to_string()));
+            args.push(remote_path(ssh_creds, destination));
+        }
+        SyncMode::Pull => {
+            args.extend(
+                source
+                    .paths()
+                    .into_iter()
+                    .map(|p| remote_path(ssh_creds, p)),
+            );
+            args.push(destination.to_string());
+        }
+    }
+
+    Ok(args)
+}
+
+/// path on the other host in the notation of rsync
+fn remote_path(ssh_creds: &SshCredentials, path: &str) -> String {
+    format!("{}@{}:{}", ssh_creds.user, ssh_creds.host, path)
+}
+
+/// write a small file on the server, creating its directory if necessary
+pub fn write_remote_file(
+    exec: &dyn Executor,
+    server: Server,
+    path: &Path,
+    content: &str,
+) -> Result<String, SyncError> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("remote file".to_string()))?;
+    let dir = match path.rsplit_once('/') {
+        Some((dir, _)) if !dir.is_empty() => dir,
+        _ => ".",
+    };
+    let script = format!(
+        "mkdir -p {} && printf '%s' {} > {}",
+        shell_quote(dir),
+        shell_quote(content),
+        shell_quote(path)
+    );
+
+    server_exec(exec, server, &["sh", "-c", &script])
+}
+
+/// compute the SHA-256 hash of a local file
+pub fn file_sha256(exec: &dyn Executor, path: &Path) -> Result<String, SyncError> {
+    let output = exec.exec(
+        "sha256sum",
+        &[path
+            .to_str()
+            .ok_or_else(|| SyncError::PathConversionError("file".to_string()))?],
     )?;
 
-    Ok(())
+    match output.split_whitespace().next() {
+        Some(hash) => Ok(hash.to_string()),
+        None => Err(SyncError::UnexpectedOutput(output)),
+    }
+}
+
+/// exclude the metadata directory of the snapshots from comparisons
+fn metadata_exclude() -> String {
+    format!("--exclude=/{}/", manifest::METADATA_DIR)
+}
+
+/// copy a local file to the server
+pub fn upload_file(
+    exec: &dyn Executor,
+    server: Server,
+    local_path: &Path,
+    remote_path: &Path,
+) -> Result<String, SyncError> {
+    let local_path = local_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("local file".to_string()))?;
+    let remote_path = remote_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("remote file".to_string()))?;
+
+    match server {
+        // rsync -e "ssh ..." ${local} ${user}@${host}:${remote}
+        Server::Remote(ssh_creds) => {
+            let ssh_command = ssh_creds.ssh_command();
+
+            exec.exec(
+                "rsync",
+                &[
+                    "-e",
+                    &ssh_command,
+                    local_path,
+                    &self::remote_path(ssh_creds, remote_path),
+                ],
+            )
+        }
+        Server::Local => exec.exec("cp", &[local_path, remote_path]),
+    }
+}
+
+/// host storing the snapshots (see `Config::server`) or host of the source (see `Config::client`)
+#[derive(Debug, Clone, Copy)]
+pub enum Server<'a> {
+    /// other host reached via ssh (the backup server in push mode, the client in pull mode)
+    Remote(&'a SshCredentials),
+    /// this host
+    Local,
+}
+
+/// run a command on the host storing the snapshots or the host of the source
+pub fn server_exec(
+    exec: &dyn Executor,
+    server: Server,
+    args: &[&str],
+) -> Result<String, SyncError> {
+    server_exec_with(exec, server, args, ExecOptions::default())
+}
+
+/// run a command on the host storing the snapshots or the host of the source with the given options
+pub fn server_exec_with(
+    exec: &dyn Executor,
+    server: Server,
+    args: &[&str],
+    options: ExecOptions,
+) -> Result<String, SyncError> {
+    match server {
+        Server::Remote(ssh_creds) => remote_exec_with(exec, ssh_creds, args, options),
+        Server::Local => match args.split_first() {
+            Some((command, args)) => exec.exec_with(command, args, options),
+            None => Err(SyncError::UnexpectedOutput("empty command".to_string())),
+        },
+    }
+}
+
+/// run a command on the other host via ssh
+pub fn remote_exec(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    args: &[&str],
+) -> Result<String, SyncError> {
+    remote_exec_with(exec, ssh_creds, args, ExecOptions::default())
+}
+
+/// run a command on the other host via ssh with the given options; the timeout of the ssh credentials applies unless the options set one
+pub fn remote_exec_with(
+    exec: &dyn Executor,
+    ssh_creds: &SshCredentials,
+    args: &[&str],
+    options: ExecOptions,
+) -> Result<String, SyncError> {
+    // ssh passes the command to the remote shell, so the arguments must be quoted
+    let quoted = args
+        .iter()
+        .map(|a| shell_quote(a))
+        .collect::<Vec<Cow<str>>>();
+    let ssh_options = ssh_creds.ssh_options();
+    let mut ssh_args = ssh_options
+        .iter()
+        .map(|o| o.as_str())
+        .collect::<Vec<&str>>();
+
+    let timeout = match &ssh_creds.timeout {
+        Some(timeout) => Some(
+            Duration::try_from(timeout)?
+                .to_std()
+                .map_err(|_| SyncError::DurationConversionError)?,
+        ),
+        None => None,
+    };
+
+    ssh_args.push(ssh_creds.host.as_str());
+    ssh_args.extend(quoted.iter().map(|a| a.as_ref()));
+
+    exec.exec_with(
+        "ssh",
+        &ssh_args[..],
+        ExecOptions {
+            timeout: options.timeout.or(timeout),
+            ..options
+        },
+    )
+}
+
+/// quote an argument for a POSIX shell, if necessary
+pub fn shell_quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c))
+    {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")))
+    }
+}
+
+/// run a hook command on the host of the source with the run information in its environment
+pub fn run_hook(
+    exec: &dyn Executor,
+    client: Server,
+    stage: HookStage,
+    hook: &Hook,
+    context: &HookContext,
+) -> Result<(), SyncError> {
+    // env BACKUP_RSYNC_HOOK=... timeout 60s sh -c "${hook.command}"
+    let env = [
+        format!("BACKUP_RSYNC_HOOK={}", stage.name()),
+        format!("BACKUP_RSYNC_JOB={}", context.job),
+        format!("BACKUP_RSYNC_SNAPSHOT={}", context.snapshot),
+        format!("BACKUP_RSYNC_STATUS={}", context.status),
+    ];
+    let timeout = match &hook.timeout {
+        Some(timeout) => Some(format!("{}s", Duration::try_from(timeout)?.num_seconds())),
+        None => None,
+    };
+    let mut args: Vec<&str> = env.iter().map(|e| e.as_str()).collect();
+
+    if let Some(timeout) = &timeout {
+        args.push("timeout");
+        args.push(timeout.as_str());
+    }
+
+    args.insert(0, "env");
+    args.push("sh");
+    args.push("-c");
+    args.push(hook.command.as_str());
+
+    match server_exec(exec, client, &args[..]) {
+        Ok(_) => Ok(()),
+        Err(e) => match hook.on_error {
+            HookFailurePolicy::Abort => Err(SyncError::HookError(
+                stage.name().to_string(),
+                e.to_string(),
+            )),
+            HookFailurePolicy::Continue => {
+                log::warn!("hook \"{}\" failed: {}", stage.name(), e);
+                Ok(())
+            }
+        },
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::custom_duration::CustomDuration;
     use chrono::TimeZone;
 
+    fn exclude_file() -> FilterRules {
+        FilterRules {
+            exclude_file: Some("exclude_file".into()),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn sync_backup() {
         let mut mock = exec_rs::MockExec::new();
@@ -171,10 +689,11 @@ mod test {
                     "--exclude-from=exclude_file",
                     "--delete-after",
                     "--delete-excluded",
+                    "--partial-dir=.rsync-partial",
+                    "--stats",
+                    "--log-file=log_file",
                     "source",
                     "ssh_user@host:destination",
-                    ">",
-                    "log_file",
                 ]
             );
             Ok("ok".to_string())
@@ -182,15 +701,267 @@ mod test {
 
         super::sync_backup(
             &mock,
-            &SshCredentials {
-                user: "ssh_user".to_string(),
-                id_file: "ssh_id_file".to_string(),
-                host: "host".to_string(),
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &"source".into(),
+            &Path::new("destination"),
+            &Path::new("log_file"),
+            &TransferLimits::default(),
+            None,
+        )
+        .unwrap();
+    }
+
+    /// executor simulating rsync exiting with the given status
+    struct Exiting {
+        status: i32,
+    }
+
+    impl Executor for Exiting {
+        fn exec_with(
+            &self,
+            _command: &str,
+            _args: &[&str],
+            options: ExecOptions,
+        ) -> Result<String, SyncError> {
+            match options.allowed_statuses.contains(&self.status) {
+                true => {
+                    if let Some(exit_status) = options.exit_status {
+                        *exit_status = self.status;
+                    }
+                    Ok("ok".to_string())
+                }
+                false => Err(SyncError::CommandFailed(
+                    "rsync".to_string(),
+                    format!("exit status: {}", self.status),
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn sync_backup_vanished() {
+        let (output, exit_status) = super::sync_backup(
+            &Exiting {
+                status: RSYNC_VANISHED,
             },
-            &Path::new("exclude_file"),
-            &Path::new("source"),
+            &SshCredentials::default(),
+            SyncMode::Push,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &"source".into(),
             &Path::new("destination"),
             &Path::new("log_file"),
+            &TransferLimits::default(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(output, "ok");
+        assert_eq!(exit_status, RSYNC_VANISHED);
+    }
+
+    #[test]
+    fn sync_backup_time_limit() {
+        let sync_backup = |limits: &TransferLimits| {
+            super::sync_backup(
+                &Exiting {
+                    status: RSYNC_TIME_LIMIT,
+                },
+                &SshCredentials::default(),
+                SyncMode::Push,
+                &RsyncOptions::default(),
+                &exclude_file(),
+                &"source".into(),
+                &Path::new("destination"),
+                &Path::new("log_file"),
+                limits,
+                None,
+            )
+        };
+
+        assert_eq!(
+            sync_backup(&TransferLimits {
+                time_limit: Some(90),
+                ..Default::default()
+            })
+            .unwrap()
+            .1,
+            RSYNC_TIME_LIMIT
+        );
+        // without a time limit, the status is a timeout of rsync
+        assert!(sync_backup(&TransferLimits::default()).is_err());
+    }
+
+    #[test]
+    fn sync_backup_with_options() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rsync");
+            assert_eq!(
+                args,
+                vec![
+                    "-ave",
+                    "ssh -l ssh_user -i ssh_id_file",
+                    "--compress",
+                    "--compress-level=3",
+                    "--exclude-from=exclude_file",
+                    "--delete-during",
+                    "--acls",
+                    "--xattrs",
+                    "--hard-links",
+                    "--sparse",
+                    "--numeric-ids",
+                    "--checksum",
+                    "--partial-dir=.rsync-partial",
+                    "--max-size=1G",
+                    "--stats",
+                    "--log-file=log_file",
+                    "source",
+                    "ssh_user@host:destination",
+                ]
+            );
+            Ok("ok".to_string())
+        });
+
+        let options: RsyncOptions = serde_json::from_str(
+            r#"{
+                "acls": true,
+                "xattrs": true,
+                "hard_links": true,
+                "sparse": true,
+                "numeric_ids": true,
+                "compression_level": 3,
+                "one_file_system": false,
+                "delete": "during",
+                "delete_excluded": false,
+                "checksum": true,
+                "partial_dir": ".rsync-partial",
+                "extra_args": ["--max-size=1G"]
+            }"#,
+        )
+        .unwrap();
+
+        super::sync_backup(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &options,
+            &exclude_file(),
+            &"source".into(),
+            Path::new("destination"),
+            Path::new("log_file"),
+            &TransferLimits::default(),
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn sync_backup_with_limits() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(
+                args[6..11],
+                [
+                    "--delete-excluded",
+                    "--partial-dir=.rsync-partial",
+                    "--bwlimit=1M",
+                    "--time-limit=90",
+                    "--stats"
+                ]
+            );
+            Ok("ok".to_string())
+        });
+
+        super::sync_backup(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &"source".into(),
+            Path::new("destination"),
+            Path::new("log_file"),
+            &TransferLimits {
+                bwlimit: Some("1M".to_string()),
+                time_limit: Some(90),
+                deadline: true,
+            },
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn sync_backup_with_progress() {
+        let mut mock = exec_rs::MockExec::new();
+        let mut progress = Vec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(
+                args[9..],
+                [
+                    "--log-file=log_file",
+                    "--info=progress2",
+                    "source",
+                    "ssh_user@host:destination"
+                ]
+            );
+            Ok("sending incremental file list\n          1,024  50%    1.00MB/s    0:00:01 (xfr#1, to-chk=1/2)\r          2,048 100%    1.00MB/s    0:00:00 (xfr#2, to-chk=0/2)\n".to_string())
+        });
+
+        super::sync_backup(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &"source".into(),
+            Path::new("destination"),
+            Path::new("log_file"),
+            &TransferLimits::default(),
+            Some(&mut |p: &TransferProgress| progress.push(p.percent)),
+        )
+        .unwrap();
+
+        assert_eq!(progress, [50, 100]);
+    }
+
+    #[test]
+    fn sync_backup_multiple_sources() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(
+                args[8..],
+                [
+                    "--stats",
+                    "--log-file=log_file",
+                    "--relative",
+                    "/home",
+                    "/etc",
+                    "ssh_user@host:destination",
+                ]
+            );
+            Ok("ok".to_string())
+        });
+
+        super::sync_backup(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &Source::Multiple(vec!["/home".to_string(), "/etc".to_string()]),
+            Path::new("destination"),
+            Path::new("log_file"),
+            &TransferLimits::default(),
+            None,
         )
         .unwrap();
     }
@@ -209,26 +980,45 @@ mod test {
                     "-i",
                     "ssh_id_file",
                     "host",
-                    "cp",
-                    "-al",
-                    "backup_path",
-                    "snapshot_path"
+                    "sh",
+                    "-c",
+                    "'if [ -e snapshot_path ]; then echo exists; else rm -rf .snapshot_path.incomplete && cp -al backup_path .snapshot_path.incomplete && mv -T .snapshot_path.incomplete snapshot_path; fi'"
                 ]
             );
-            Ok("ok".to_string())
+            Ok(String::new())
         });
 
         super::create_snapshot(
             &mock,
-            &SshCredentials {
-                user: "ssh_user".to_string(),
-                id_file: "ssh_id_file".to_string(),
-                host: "host".to_string(),
-            },
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
             &Path::new("backup_path"),
             &Path::new("snapshot_path"),
         )
         .unwrap();
+
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .returning(|_, _| Ok("exists\n".to_string()));
+
+        assert!(matches!(
+            super::create_snapshot(
+                &mock,
+                Server::Local,
+                &Path::new("backup_path"),
+                &Path::new("snapshot path"),
+            ),
+            Err(SyncError::SnapshotExists(path)) if path == "snapshot path"
+        ));
+    }
+
+    #[test]
+    fn incomplete_snapshot_path() {
+        assert_eq!(
+            super::incomplete_snapshot_path(Path::new("snapshot/laptop/2022-11-01T1230")),
+            Path::new("snapshot/laptop/.2022-11-01T1230.incomplete")
+        );
     }
 
     #[test]
@@ -250,28 +1040,31 @@ mod test {
                     "snapshot_path"
                 ]
             );
-            Ok("2022-11-02T21:22:10Z_test1\n2022-11-01T21:22:10Z_test2\n".to_string())
+            Ok("2022-11-02T21:22:10Z_test_user\nlost+found\n2022-11-01T21:22:10Z_test_user\n2022-11-01T22:22:10Z_other\n".to_string())
         });
 
         let res = super::get_snapshots(
             &mock,
-            &SshCredentials {
-                user: "ssh_user".to_string(),
-                id_file: "ssh_id_file".to_string(),
-                host: "host".to_string(),
-            },
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
             &Path::new("snapshot_path"),
+            &NamingScheme::new(
+                crate::naming::DEFAULT_TEMPLATE,
+                "laptop",
+                "job",
+                "test_user",
+            )
+            .unwrap(),
         )
         .unwrap();
         assert_eq!(
             vec![
                 (
                     Utc.ymd(2022, 11, 02).and_hms(21, 22, 10),
-                    "2022-11-02T21:22:10Z_test1".to_string()
+                    "2022-11-02T21:22:10Z_test_user".to_string()
                 ),
                 (
                     Utc.ymd(2022, 11, 01).and_hms(21, 22, 10),
-                    "2022-11-01T21:22:10Z_test2".to_string()
+                    "2022-11-01T21:22:10Z_test_user".to_string()
                 )
             ],
             res
@@ -302,13 +1095,355 @@ mod test {
 
         super::delete_snapshot(
             &mock,
-            &SshCredentials {
-                user: "ssh_user".to_string(),
-                id_file: "ssh_id_file".to_string(),
-                host: "host".to_string(),
-            },
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
             &Path::new("snapshot_path"),
         )
         .unwrap();
     }
+
+    #[test]
+    fn run_hook() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "env");
+            assert_eq!(
+                args,
+                vec![
+                    "BACKUP_RSYNC_HOOK=pre_sync",
+                    "BACKUP_RSYNC_JOB=job",
+                    "BACKUP_RSYNC_SNAPSHOT=snapshot/2022-11-02T21:22:10Z_test",
+                    "BACKUP_RSYNC_STATUS=running",
+                    "timeout",
+                    "120s",
+                    "sh",
+                    "-c",
+                    "pg_dumpall > /var/backup/db.sql",
+                ]
+            );
+            Ok("".to_string())
+        });
+
+        super::run_hook(
+            &mock,
+            Server::Local,
+            HookStage::PreSync,
+            &Hook {
+                command: "pg_dumpall > /var/backup/db.sql".to_string(),
+                timeout: Some(CustomDuration::minutes(2)),
+                on_error: HookFailurePolicy::Abort,
+            },
+            &HookContext {
+                job: "job",
+                snapshot: "snapshot/2022-11-02T21:22:10Z_test",
+                status: "running",
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn run_hook_remote() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(
+                args[4..],
+                [
+                    "client",
+                    "env",
+                    "BACKUP_RSYNC_HOOK=pre_sync",
+                    "BACKUP_RSYNC_JOB=job",
+                    "BACKUP_RSYNC_SNAPSHOT=snapshot/2022-11-02T21:22:10Z_test",
+                    "BACKUP_RSYNC_STATUS=running",
+                    "sh",
+                    "-c",
+                    "'systemctl stop db'",
+                ]
+            );
+            Ok("".to_string())
+        });
+
+        super::run_hook(
+            &mock,
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "client")),
+            HookStage::PreSync,
+            &Hook {
+                command: "systemctl stop db".to_string(),
+                timeout: None,
+                on_error: HookFailurePolicy::Abort,
+            },
+            &HookContext {
+                job: "job",
+                snapshot: "snapshot/2022-11-02T21:22:10Z_test",
+                status: "running",
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn get_disk_usage() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(
+                args[..7],
+                ["-l", "ssh_user", "-i", "ssh_id_file", "host", "sh", "-c"]
+            );
+            assert!(args[7].starts_with(r"'for d in snapshot/a '\''snapshot/b c'\''; do"));
+            Ok("1024\t10\n2048\t0\n".to_string())
+        });
+
+        let res = super::get_disk_usage(
+            &mock,
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
+            &[Path::new("snapshot/a"), Path::new("snapshot/b c")],
+        )
+        .unwrap();
+
+        assert_eq!(res, vec![(1024, 10), (2048, 0)]);
+    }
+
+    #[test]
+    fn shell_quote() {
+        assert_eq!(
+            super::shell_quote("snapshot/2022-11-02T21:22:10Z_test"),
+            "snapshot/2022-11-02T21:22:10Z_test"
+        );
+        assert_eq!(super::shell_quote("a b"), "'a b'");
+        assert_eq!(super::shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(super::shell_quote(""), "''");
+    }
+
+    #[test]
+    fn compare_source() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rsync");
+            assert_eq!(
+                args,
+                vec![
+                    "-ani",
+                    "-e",
+                    "ssh -l ssh_user -i ssh_id_file",
+                    "--delete",
+                    "--exclude=/.backup-rsync/",
+                    "--exclude-from=exclude_file",
+                    "source",
+                    "ssh_user@host:snapshot/2022-11-02T21:22:10Z_test",
+                ]
+            );
+            Ok("".to_string())
+        });
+
+        super::compare_source(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &exclude_file(),
+            &"source".into(),
+            Path::new("snapshot/2022-11-02T21:22:10Z_test"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn verify_snapshot_files_from() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rsync");
+            assert_eq!(
+                args,
+                vec![
+                    "-anci",
+                    "-e",
+                    "ssh -l ssh_user -i ssh_id_file",
+                    "--exclude=/.backup-rsync/",
+                    "--exclude-from=exclude_file",
+                    "--files-from=files",
+                    "--from0",
+                    "source/",
+                    "ssh_user@host:snapshot/2022-11-02T21:22:10Z_test",
+                ]
+            );
+            Ok("".to_string())
+        });
+
+        super::verify_snapshot(
+            &mock,
+            &SshCredentials::test("ssh_user", "ssh_id_file", "host"),
+            SyncMode::Push,
+            &exclude_file(),
+            &"source/".into(),
+            Path::new("snapshot/2022-11-02T21:22:10Z_test"),
+            Some(Path::new("files")),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn write_remote_file() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(
+                args,
+                vec![
+                    "-l",
+                    "ssh_user",
+                    "-i",
+                    "ssh_id_file",
+                    "host",
+                    "sh",
+                    "-c",
+                    r#"'mkdir -p snapshot/a/.backup-rsync && printf '\''%s'\'' '\''{"a":1}'\'' > snapshot/a/.backup-rsync/metadata.json'"#,
+                ]
+            );
+            Ok("".to_string())
+        });
+
+        super::write_remote_file(
+            &mock,
+            Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
+            Path::new("snapshot/a/.backup-rsync/metadata.json"),
+            r#"{"a":1}"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn file_sha256() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "sha256sum");
+            assert_eq!(args, vec!["exclude_file"]);
+            Ok("e3b0c44298fc1c149afbf4c8996fb924  exclude_file\n".to_string())
+        });
+
+        assert_eq!(
+            super::file_sha256(&mock, Path::new("exclude_file")).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb924"
+        );
+    }
+
+    #[test]
+    fn get_snapshot_entries() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(
+                args[5..],
+                [
+                    "find",
+                    "snapshot_path",
+                    "-mindepth",
+                    "2",
+                    "-maxdepth",
+                    "2",
+                    "-printf",
+                    "'%P\\n'"
+                ]
+            );
+            Ok("laptop/2022-11-01T1230\nlaptop/2022-11-02T1230\n".to_string())
+        });
+
+        assert_eq!(
+            super::get_snapshot_entries(
+                &mock,
+                Server::Remote(&SshCredentials::test("ssh_user", "ssh_id_file", "host")),
+                Path::new("snapshot_path"),
+                2,
+            )
+            .unwrap(),
+            vec!["laptop/2022-11-01T1230", "laptop/2022-11-02T1230"]
+        );
+    }
+
+    #[test]
+    fn list_excluded() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rsync");
+            assert_eq!(
+                args,
+                vec![
+                    "-r",
+                    "--list-only",
+                    "--debug=FILTER1",
+                    "--exclude=*.tmp",
+                    "--exclude-from=exclude_file",
+                    "/home",
+                ]
+            );
+            Ok(String::new())
+        });
+
+        super::list_excluded(
+            &mock,
+            &SshCredentials::default(),
+            SyncMode::Push,
+            &FilterRules {
+                exclude: vec!["*.tmp".to_string()],
+                ..exclude_file()
+            },
+            &"/home".into(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn sync_backup_pull() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(
+                args[8..],
+                [
+                    "--stats",
+                    "--log-file=log_file",
+                    "--relative",
+                    "client@host:/home",
+                    "client@host:/etc",
+                    "destination",
+                ]
+            );
+            Ok("ok".to_string())
+        });
+
+        super::sync_backup(
+            &mock,
+            &SshCredentials::test("client", "id_file", "host"),
+            SyncMode::Pull,
+            &RsyncOptions::default(),
+            &exclude_file(),
+            &Source::Multiple(vec!["/home".to_string(), "/etc".to_string()]),
+            Path::new("destination"),
+            Path::new("log_file"),
+            &TransferLimits::default(),
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn server_exec_local() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rm");
+            assert_eq!(args, vec!["-r", "snapshot/a b"]);
+            Ok(String::new())
+        });
+
+        super::delete_snapshot(&mock, Server::Local, Path::new("snapshot/a b")).unwrap();
+    }
 }
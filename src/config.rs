@@ -1,27 +1,202 @@
-use crate::{custom_duration::CustomDuration, ssh_credentials::SshCredentials};
-use anyhow::{Context, Result};
+use crate::{
+    bandwidth::Bandwidth,
+    check::CheckThresholds,
+    clock::{ClockSkewPolicy, SnapshotTimeZone},
+    commands::Server,
+    config_file,
+    custom_duration::CustomDuration,
+    history,
+    hooks::Hooks,
+    hostname,
+    notification::{Notification, NotifyWhen},
+    rsync_options::RsyncOptions,
+    schedule::Schedule,
+    secret,
+    source::Source,
+    ssh_credentials::SshCredentials,
+};
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
 use serde::Deserialize;
-use std::{fs::File, path::Path};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Default)]
 pub struct Config {
-    pub source: String,
+    /// version of the configuration format; older files are upgraded when read (see `migrate-config`)
+    #[serde(default)]
+    pub version: u64,
+    /// name of the job; defaults to the snapshot suffix
+    pub name: Option<String>,
+    /// "push" (default) to sync from this host to the server or "pull" to sync from the client into local directories
+    #[serde(default)]
+    pub mode: SyncMode,
+    /// source directory or list of absolute source paths (synced with `--relative`)
+    pub source: Source,
     pub destination: String,
-    pub exclude_file: String,
+    /// file with exclude rules (see `rsync --exclude-from`)
+    pub exclude_file: Option<String>,
+    /// include patterns; take precedence over the exclude patterns and the exclude file
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// exclude patterns; take precedence over the exclude file
+    #[serde(default)]
+    pub exclude: Vec<String>,
     pub log_file: String,
+    /// credentials for the server in push mode and for the client in pull mode
     pub ssh_credentials: SshCredentials,
     pub snapshot: String,
     pub snapshot_suffix: String,
+    /// template for the names of the snapshots (e.g. "{host}/{date:%Y-%m-%dT%H%M}-{suffix}"); defaults to "{date}_{suffix}"
+    pub snapshot_name: Option<String>,
+    /// time zone of the dates in the snapshot names and listings ("utc", "local" or an offset like "+01:00")
+    #[serde(default)]
+    pub time_zone: SnapshotTimeZone,
+    /// maximum difference between the local clock and the clock of the server; not checked if missing
+    pub max_clock_skew: Option<CustomDuration>,
+    #[serde(default)]
+    pub on_clock_skew: ClockSkewPolicy,
+    /// options passed to rsync when syncing the backup
+    #[serde(default)]
+    pub rsync: RsyncOptions,
+    /// bandwidth limits and deadline of the transfer; times of day are in `time_zone`
+    #[serde(default)]
+    pub bandwidth: Bandwidth,
     pub policy: Vec<CustomDuration>,
+    /// names of snapshots that are never pruned
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub notifications: Vec<Notification>,
+    /// store a manifest with the hashes of all files in each snapshot
+    #[serde(default)]
+    pub manifest: bool,
+    /// thresholds of the `check` command
+    #[serde(default)]
+    pub check: CheckThresholds,
+    /// when the daemon runs the job
+    pub schedule: Option<Schedule>,
+    /// local directory for state kept between runs
+    pub state_dir: Option<String>,
+    /// file for the Prometheus textfile collector (e.g. "/var/lib/node_exporter/backup.prom")
+    pub metrics_file: Option<String>,
+}
+
+/// direction of the backup
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// the client syncs its files to the server; snapshots are created and pruned on the server
+    #[default]
+    Push,
+    /// the server syncs the files of the client; snapshots are created and pruned locally
+    Pull,
 }
 
 impl Config {
+    /// read the configuration with its base configurations and overlays merged; "{hostname}" is replaced by the name of the local host and strings of the form "env:<variable>" and "file:<path>" by the value of the variable or the content of the file
     pub fn read_from_file(filepath: &Path) -> Result<Self> {
-        let file = File::open(filepath).context(format!(
-            "could not open configuration file \"{}\"",
-            filepath.to_string_lossy()
-        ))?;
+        let mut value = config_file::load(filepath, &hostname::hostname())?;
+
+        secret::resolve(&mut value)?;
+
+        let config: Self = serde_json::from_value(value)?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// check settings depending on each other
+    fn validate(&self) -> Result<()> {
+        // without a state directory, there is no previous outcome to compare against
+        if self.state_dir.is_none()
+            && self
+                .notifications
+                .iter()
+                .any(|n| n.when == NotifyWhen::OnStateChange)
+        {
+            bail!("notifications sent \"on_state_change\" require a \"state_dir\"");
+        }
+
+        Ok(())
+    }
+
+    /// host storing the destination and the snapshots
+    pub fn server(&self) -> Server<'_> {
+        match self.mode {
+            SyncMode::Push => Server::Remote(&self.ssh_credentials),
+            SyncMode::Pull => Server::Local,
+        }
+    }
+
+    /// host of the source, where the hooks are run
+    pub fn client(&self) -> Server<'_> {
+        match self.mode {
+            SyncMode::Push => Server::Local,
+            SyncMode::Pull => Server::Remote(&self.ssh_credentials),
+        }
+    }
+
+    /// name of the host of the source (this host in push mode, the client in pull mode)
+    pub fn source_host(&self) -> String {
+        match self.mode {
+            SyncMode::Push => hostname::hostname(),
+            SyncMode::Pull => self.ssh_credentials.host.clone(),
+        }
+    }
+
+    pub fn job_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.snapshot_suffix)
+    }
+
+    /// path of the file holding the state of the job (if a state directory is configured)
+    pub fn state_file(&self) -> Option<PathBuf> {
+        self.state_dir
+            .as_ref()
+            .map(|dir| Path::new(dir).join(format!("{}.json", self.job_name())))
+    }
+
+    /// path of the file holding the hashes of the manifests of the job (if a state directory is configured; see `ManifestHashes`)
+    pub fn manifest_hashes_file(&self) -> Option<PathBuf> {
+        self.state_dir
+            .as_ref()
+            .map(|dir| Path::new(dir).join(format!("{}.manifests.json", self.job_name())))
+    }
+
+    /// path of the run history shared by the jobs of the state directory (if a state directory is configured)
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.state_dir
+            .as_ref()
+            .map(|dir| Path::new(dir).join(history::HISTORY_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::NotificationSink;
+
+    #[test]
+    fn validate() {
+        let config = Config {
+            notifications: vec![Notification {
+                when: NotifyWhen::OnStateChange,
+                sink: NotificationSink::Command {
+                    command: "notify-send backup".to_string(),
+                },
+            }],
+            ..Default::default()
+        };
 
-        Ok(serde_json::from_reader(file)?)
+        assert!(config.validate().is_err());
+        assert!(Config {
+            state_dir: Some("/var/lib/backup-rsync".to_string()),
+            ..config
+        }
+        .validate()
+        .is_ok());
+        assert!(Config::default().validate().is_ok());
     }
 }
@@ -0,0 +1,362 @@
+use crate::config::Config;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// version of the configuration format written by this version of the tool
+pub const CONFIG_VERSION: u64 = 1;
+/// steps upgrading the configuration; the step at index i upgrades version i to version i + 1
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    // files without a version predate the version field and need no changes
+    |_| {},
+];
+/// key holding the version of the configuration format
+const VERSION_KEY: &str = "version";
+
+/// key listing the base configurations a file is merged onto (required)
+const EXTENDS_KEY: &str = "extends";
+/// key listing the configurations merged onto a file (skipped if missing; e.g. "hosts/{hostname}.json")
+const OVERLAYS_KEY: &str = "overlays";
+/// key of the retention periods, which are combined instead of replaced when merging
+const POLICY_KEY: &str = "policy";
+/// variable replaced by the name of the local host in all strings
+const HOSTNAME_VARIABLE: &str = "{hostname}";
+
+/// files whose outdated version was reported (the daemon reads the configurations before each check for due jobs)
+static REPORTED_OUTDATED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// JSON schema of the configuration files
+///
+/// Required fields are not marked, as base configurations and overlays contain only some of the fields; `extends` and `overlays` are added, as they are removed before the configuration is deserialized.
+pub fn schema() -> Result<Value> {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config))?;
+    let paths = |description: &str| {
+        json!({
+            "description": description,
+            "anyOf": [
+                {"type": "string"},
+                {"type": "array", "items": {"type": "string"}}
+            ]
+        })
+    };
+
+    if let Value::Object(map) = &mut schema {
+        map.remove("required");
+
+        if let Some(Value::Object(properties)) = map.get_mut("properties") {
+            properties.insert(
+                EXTENDS_KEY.to_string(),
+                paths("base configurations merged first (a path or a list of paths)"),
+            );
+            properties.insert(
+                OVERLAYS_KEY.to_string(),
+                paths("configurations merged last if they exist (a path or a list of paths, e.g. \"hosts/{hostname}.json\")"),
+            );
+        }
+    }
+
+    Ok(schema)
+}
+
+/// read a configuration file with its base configurations and overlays merged and the variables substituted
+///
+/// Relative paths in "extends" and "overlays" are relative to the directory of the file naming them.
+pub fn load(path: &Path, hostname: &str) -> Result<Value> {
+    let mut value = load_layers(path, hostname, &mut Vec::new())?;
+
+    substitute(&mut value, hostname);
+
+    Ok(value)
+}
+
+fn load_layers(path: &Path, hostname: &str, stack: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = path.canonicalize().context(format!(
+        "could not open configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+
+    if stack.contains(&canonical) {
+        bail!(
+            "configuration file \"{}\" extends itself",
+            path.to_string_lossy()
+        );
+    }
+
+    let file = File::open(path).context(format!(
+        "could not open configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+    let mut value: Value = serde_json::from_reader(file).context(format!(
+        "could not parse configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+    let version = migrate(&mut value).context(format!(
+        "could not migrate configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+
+    if version < CONFIG_VERSION && first_report(&canonical) {
+        log::info!(
+            "configuration file \"{}\" has version {}; run \"backup-rsync migrate-config\" to upgrade it",
+            path.to_string_lossy(),
+            version
+        );
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let (extends, overlays) = match &mut value {
+        Value::Object(map) => (
+            paths(map.remove(EXTENDS_KEY), EXTENDS_KEY)?,
+            paths(map.remove(OVERLAYS_KEY), OVERLAYS_KEY)?,
+        ),
+        _ => bail!(
+            "configuration file \"{}\" does not contain an object",
+            path.to_string_lossy()
+        ),
+    };
+
+    stack.push(canonical);
+
+    let mut res = Value::Object(Map::new());
+
+    for base in extends {
+        let base = dir.join(base.replace(HOSTNAME_VARIABLE, hostname));
+
+        merge(&mut res, load_layers(&base, hostname, stack)?);
+    }
+
+    merge(&mut res, value);
+
+    for overlay in overlays {
+        let overlay = dir.join(overlay.replace(HOSTNAME_VARIABLE, hostname));
+
+        match overlay.exists() {
+            true => merge(&mut res, load_layers(&overlay, hostname, stack)?),
+            false => log::debug!("skipping missing overlay \"{}\"", overlay.to_string_lossy()),
+        }
+    }
+
+    stack.pop();
+
+    Ok(res)
+}
+
+/// upgrade a configuration to the current version; returns the original version
+pub fn migrate(value: &mut Value) -> Result<u64> {
+    let map = match value {
+        Value::Object(map) => map,
+        _ => bail!("the configuration is not an object"),
+    };
+    let version = match map.get(VERSION_KEY) {
+        None => 0,
+        Some(version) => match version.as_u64() {
+            Some(version) => version,
+            None => bail!("invalid version {}", version),
+        },
+    };
+
+    if version > CONFIG_VERSION {
+        bail!(
+            "version {} is newer than the supported version {}",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(map);
+    }
+
+    // the version is put first to be easily found in upgraded files
+    let mut migrated = Map::new();
+
+    migrated.insert(VERSION_KEY.to_string(), Value::from(CONFIG_VERSION));
+    migrated.extend(
+        std::mem::take(map)
+            .into_iter()
+            .filter(|(k, _)| k != VERSION_KEY),
+    );
+    *map = migrated;
+
+    Ok(version)
+}
+
+/// upgrade a configuration file to the current version; the original file is kept with the extension ".bak"
+///
+/// Returns the upgraded configuration or `None` if the file already has the current version. Base configurations and overlays are not upgraded.
+pub fn migrate_file(path: &Path, dry_run: bool) -> Result<Option<String>> {
+    let content = fs::read_to_string(path).context(format!(
+        "could not open configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+    let mut value: Value = serde_json::from_str(&content).context(format!(
+        "could not parse configuration file \"{}\"",
+        path.to_string_lossy()
+    ))?;
+
+    if migrate(&mut value)? == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    let migrated = serde_json::to_string_pretty(&value)? + "\n";
+
+    if !dry_run {
+        let mut backup = path.as_os_str().to_owned();
+
+        backup.push(".bak");
+        fs::write(&backup, content)?;
+        fs::write(path, &migrated)?;
+    }
+
+    Ok(Some(migrated))
+}
+
+/// whether the outdated version of the file was not reported yet
+fn first_report(path: &Path) -> bool {
+    match REPORTED_OUTDATED.lock() {
+        Ok(mut reported) => reported.insert(path.to_path_buf()),
+        Err(_) => true,
+    }
+}
+
+/// get the paths of "extends" or "overlays" (a single path or a list)
+fn paths(value: Option<Value>, key: &str) -> Result<Vec<String>> {
+    match value {
+        None => Ok(Vec::new()),
+        Some(Value::String(path)) => Ok(vec![path]),
+        Some(value) => serde_json::from_value(value)
+            .context(format!("\"{}\" must be a path or a list of paths", key)),
+    }
+}
+
+/// merge the overlay onto the base; objects are merged recursively, the entries of the `policy` lists are combined (see `merge_policy`) and all other values (including other lists) are replaced
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base) if key == POLICY_KEY => merge_policy(base, value),
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// add the retention periods of the overlay that the base does not contain yet, so that e.g. a host can keep its snapshots for a year in addition to the periods of the base configuration
+fn merge_policy(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Array(base), Value::Array(overlay)) => {
+            for entry in overlay {
+                if !base.contains(&entry) {
+                    base.push(entry);
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// replace the host name variable in all strings
+fn substitute(value: &mut Value, hostname: &str) {
+    match value {
+        Value::String(s) => {
+            if s.contains(HOSTNAME_VARIABLE) {
+                *s = s.replace(HOSTNAME_VARIABLE, hostname);
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| substitute(v, hostname)),
+        Value::Object(values) => values.values_mut().for_each(|v| substitute(v, hostname)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn load() {
+        let dir = std::env::temp_dir().join(format!("backup-rsync-config-{}", std::process::id()));
+
+        fs::create_dir_all(dir.join("hosts")).unwrap();
+        fs::write(
+            dir.join("base.json"),
+            r#"{
+                "source": "/home",
+                "snapshot_suffix": "{hostname}",
+                "ssh_credentials": {"user": "backup", "id_file": "/root/.ssh/id_backup", "host": "backup1"},
+                "policy": [{"days": 1}, {"weeks": 1}],
+                "overlays": ["hosts/{hostname}.json", "hosts/missing.json"]
+            }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("hosts/laptop.json"),
+            r#"{"ssh_credentials": {"host": "backup2"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("laptop.json"),
+            r#"{
+                "extends": "base.json",
+                "destination": "/srv/backup/{hostname}/current",
+                "policy": [{"days": 2}, {"weeks": 1}]
+            }"#,
+        )
+        .unwrap();
+        fs::write(dir.join("loop.json"), r#"{"extends": ["loop.json"]}"#).unwrap();
+
+        let value = super::load(&dir.join("laptop.json"), "laptop").unwrap();
+        let looped = super::load(&dir.join("loop.json"), "laptop");
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "source": "/home",
+                "snapshot_suffix": "laptop",
+                "ssh_credentials": {"user": "backup", "id_file": "/root/.ssh/id_backup", "host": "backup2"},
+                "policy": [{"days": 1}, {"weeks": 1}, {"days": 2}],
+                "destination": "/srv/backup/laptop/current"
+            })
+        );
+        assert!(looped.is_err());
+    }
+
+    #[test]
+    fn schema() {
+        let schema = super::schema().unwrap();
+
+        assert!(schema.get("required").is_none());
+        assert!(schema["properties"]["source"].is_object());
+        assert!(schema["properties"]["extends"]["anyOf"].is_array());
+        assert!(schema["properties"]["overlays"]["anyOf"].is_array());
+    }
+
+    #[test]
+    fn migrate() {
+        let mut value = json!({"source": "/home", "snapshot_suffix": "laptop"});
+
+        assert_eq!(super::migrate(&mut value).unwrap(), 0);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"version":1,"source":"/home","snapshot_suffix":"laptop"}"#
+        );
+        assert_eq!(super::migrate(&mut value).unwrap(), 1);
+        assert!(super::migrate(&mut json!({"version": 2})).is_err());
+        assert!(super::migrate(&mut json!({"version": "1"})).is_err());
+    }
+}
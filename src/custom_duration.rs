@@ -1,9 +1,10 @@
 use crate::sync_error::SyncError;
 use chrono::Duration;
+use schemars::JsonSchema;
 use serde::Deserialize;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, fmt};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
 pub struct CustomDuration {
     minutes: Option<i64>,
     hours: Option<i64>,
@@ -49,6 +50,26 @@ impl CustomDuration {
     }
 }
 
+impl fmt::Display for CustomDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = [
+            (self.weeks, "w"),
+            (self.days, "d"),
+            (self.hours, "h"),
+            (self.minutes, "m"),
+        ]
+        .iter()
+        .filter_map(|(value, unit)| value.map(|v| format!("{}{}", v, unit)))
+        .collect::<Vec<String>>();
+
+        if parts.is_empty() {
+            write!(f, "0m")
+        } else {
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+}
+
 impl TryFrom<&CustomDuration> for Duration {
     type Error = SyncError;
 
@@ -113,4 +134,19 @@ mod tests {
         let test: Duration = (&CustomDuration::weeks(5)).try_into().unwrap();
         assert_eq!(Duration::weeks(5), test);
     }
+
+    #[test]
+    fn display() {
+        assert_eq!(CustomDuration::minutes(30).to_string(), "30m");
+        assert_eq!(
+            CustomDuration {
+                minutes: None,
+                hours: Some(12),
+                days: None,
+                weeks: Some(1),
+            }
+            .to_string(),
+            "1w 12h"
+        );
+    }
 }
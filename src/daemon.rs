@@ -0,0 +1,264 @@
+use crate::{
+    clock::SnapshotTimeZone,
+    config::Config,
+    executor::{self, Executor, ProcessExecutor},
+    schedule::Schedule,
+    state::JobState,
+    sync::Sync,
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// interval at which the daemon checks for due jobs; also bounds the delay after a resume
+const TICK: Duration = Duration::from_secs(60);
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// job scheduled by the daemon
+struct Job {
+    config_path: PathBuf,
+    last_run: Option<DateTime<Utc>>,
+    /// start of the scheduling; jobs without a recorded run are scheduled from this time
+    since: DateTime<Utc>,
+    /// reason for deferring the due run; logged once
+    deferred: Option<&'static str>,
+}
+
+impl Job {
+    fn new(config_path: &Path) -> Result<Self> {
+        let config = Config::read_from_file(config_path)?;
+
+        if config.schedule.is_none() {
+            bail!(
+                "no schedule configured in \"{}\"",
+                config_path.to_string_lossy()
+            );
+        }
+
+        // missed runs can only be caught up after a restart if the state is persisted
+        let last_run = match config.state_file() {
+            Some(path) => JobState::read(&path)?.last_outcome.map(|o| o.start),
+            None => {
+                log::warn!(
+                    "no state directory configured for job \"{}\"; missed runs are not caught up after a restart",
+                    config.job_name()
+                );
+                None
+            }
+        };
+
+        Ok(Job {
+            config_path: config_path.to_path_buf(),
+            last_run,
+            since: Utc::now(),
+            deferred: None,
+        })
+    }
+
+    /// whether the scheduled time of the next run has passed
+    fn due(
+        &self,
+        schedule: &Schedule,
+        time_zone: SnapshotTimeZone,
+        now: &DateTime<Utc>,
+    ) -> Result<bool> {
+        Ok(schedule.next_run(self.last_run, &self.since, now, time_zone)? <= *now)
+    }
+
+    /// run the job if it is due; the configuration is re-read, so that changes apply without a restart
+    fn tick(&mut self, exec: &dyn Executor, now: &DateTime<Utc>) -> Result<()> {
+        let config = Config::read_from_file(&self.config_path)?;
+        let schedule = match &config.schedule {
+            Some(schedule) => schedule,
+            None => bail!("no schedule configured"),
+        };
+
+        if !self.due(schedule, config.time_zone, now)? {
+            return Ok(());
+        }
+
+        let deferred = if schedule.skip_on_battery && on_battery(Path::new(POWER_SUPPLY_DIR)) {
+            Some("on battery")
+        } else if schedule.skip_on_metered && metered(exec) {
+            Some("metered connection")
+        } else {
+            None
+        };
+
+        if let Some(reason) = deferred {
+            if self.deferred != deferred {
+                log::info!("deferring job \"{}\" ({})", config.job_name(), reason);
+            }
+            self.deferred = deferred;
+
+            return Ok(());
+        }
+
+        let job_name = config.job_name().to_string();
+
+        self.deferred = None;
+        self.last_run = Some(*now);
+        log::info!("running job \"{}\"", job_name);
+
+        // the outcome is reported by the run itself
+        if let Err(e) = Sync::new(config).execute() {
+            log::error!("job \"{}\" failed: {}", job_name, e);
+        }
+
+        Ok(())
+    }
+}
+
+/// run the jobs of the configuration files according to their schedules until SIGINT or SIGTERM is received
+pub fn run(config_paths: &[PathBuf]) -> Result<()> {
+    let exec = ProcessExecutor {};
+    let mut jobs = config_paths
+        .iter()
+        .map(|path| {
+            Job::new(path).context(format!("could not load job \"{}\"", path.to_string_lossy()))
+        })
+        .collect::<Result<Vec<Job>>>()?;
+
+    log::info!("scheduling {} jobs", jobs.len());
+
+    loop {
+        // jobs run one after the other, so the time is taken for each job
+        for job in &mut jobs {
+            if executor::interrupted() {
+                log::info!("interrupted; stopping");
+                return Ok(());
+            }
+            if let Err(e) = job.tick(&exec, &Utc::now()) {
+                log::error!(
+                    "error scheduling \"{}\": {:#}",
+                    job.config_path.to_string_lossy(),
+                    e
+                );
+            }
+        }
+
+        // sleep in short steps to stop promptly when interrupted
+        let wake = Instant::now() + TICK;
+
+        while Instant::now() < wake && !executor::interrupted() {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+/// whether the host runs on battery, i.e. a battery is present, but no mains adapter is online
+pub fn on_battery(power_supply_dir: &Path) -> bool {
+    let supplies = match fs::read_dir(power_supply_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| {
+                let read = |name: &str| fs::read_to_string(e.path().join(name)).unwrap_or_default();
+
+                (
+                    read("type").trim().to_string(),
+                    read("online").trim() == "1",
+                )
+            })
+            .collect::<Vec<(String, bool)>>(),
+        Err(_) => return false,
+    };
+
+    supplies.iter().any(|(kind, _)| kind == "Battery")
+        && !supplies
+            .iter()
+            .any(|(kind, online)| kind == "Mains" && *online)
+}
+
+/// whether the network connection is metered according to NetworkManager; false if it cannot be determined
+pub fn metered(exec: &dyn Executor) -> bool {
+    match exec.exec(
+        "busctl",
+        &[
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ],
+    ) {
+        Ok(output) => parse_metered(&output),
+        Err(e) => {
+            log::debug!(
+                "could not determine whether the connection is metered: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// parse the metered property of NetworkManager (e.g. "u 1"); 1 is metered, 3 is guessed to be metered
+fn parse_metered(output: &str) -> bool {
+    matches!(output.trim().strip_prefix("u "), Some("1" | "3"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn on_battery() {
+        let dir = std::env::temp_dir().join(format!("backup-rsync-power-{}", std::process::id()));
+
+        assert!(!super::on_battery(&dir));
+
+        for (name, kind, online) in [("BAT0", "Battery", None), ("AC", "Mains", Some("0"))] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("type"), format!("{}\n", kind)).unwrap();
+
+            if let Some(online) = online {
+                fs::write(dir.join(name).join("online"), online).unwrap();
+            }
+        }
+
+        assert!(super::on_battery(&dir));
+        fs::write(dir.join("AC").join("online"), "1\n").unwrap();
+        assert!(!super::on_battery(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn due() {
+        let schedule: Schedule = serde_json::from_str(r#"{"cron": "30 2 * * *"}"#).unwrap();
+        let start = Utc.ymd(2022, 11, 3).and_hms(2, 0, 0);
+        let job = Job {
+            config_path: PathBuf::from("job.json"),
+            last_run: None,
+            since: start,
+            deferred: None,
+        };
+
+        // the daemon ticks every minute without a recorded run
+        let due = (0..60)
+            .map(|minute| start + chrono::Duration::minutes(minute))
+            .find(|now| job.due(&schedule, SnapshotTimeZone::Utc, now).unwrap());
+
+        assert_eq!(due, Some(Utc.ymd(2022, 11, 3).and_hms(2, 30, 0)));
+    }
+
+    #[test]
+    fn metered() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "busctl");
+            assert_eq!(args[4], "Metered");
+            Ok("u 3\n".to_string())
+        });
+
+        assert!(super::metered(&mock));
+        assert!(!parse_metered("u 4"));
+        assert!(!parse_metered(""));
+    }
+}
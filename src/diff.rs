@@ -0,0 +1,252 @@
+use crate::{
+    commands, config::Config, executor::Executor, filter::FilterRules, manifest,
+    sync_error::SyncError,
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// differences between two snapshots or a snapshot and the source
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl SnapshotDiff {
+    /// parse the output of `rsync --itemize-changes --dry-run --delete` from the newer to the older tree
+    pub fn parse_itemized(output: &str) -> Self {
+        let mut diff = SnapshotDiff::default();
+
+        for line in output.lines() {
+            if let Some(path) = line.strip_prefix("*deleting") {
+                diff.removed.push(path.trim_start().to_string());
+                continue;
+            }
+
+            let (item, path) = match (line.get(..11), line.get(12..)) {
+                (Some(item), Some(path)) if !path.is_empty() => (item, path),
+                _ => continue,
+            };
+            let mut chars = item.chars();
+            let (update, file_type) = match (chars.next(), chars.next()) {
+                (Some(update), Some(file_type)) => (update, file_type),
+                _ => continue,
+            };
+
+            if !"<>ch.".contains(update) || !"fdLDS".contains(file_type) {
+                continue;
+            }
+
+            // symbolic links are listed as "link -> target"
+            let path = match file_type {
+                'L' => path.split(" -> ").next().unwrap_or(path),
+                _ => path,
+            };
+
+            if item[2..].starts_with('+') {
+                diff.added.push(path.to_string());
+            } else if file_type != 'd' && item[2..].chars().any(|c| c != '.' && c != ' ') {
+                // changes of directory attributes (e.g. times) are not reported
+                diff.modified.push(path.to_string());
+            }
+        }
+
+        diff
+    }
+
+    /// keep only the paths starting with one of the prefixes (all paths if there are no prefixes)
+    pub fn filter(self, prefixes: &[String]) -> Self {
+        if prefixes.is_empty() {
+            return self;
+        }
+
+        let keep = |paths: Vec<String>| {
+            paths
+                .into_iter()
+                .filter(|p| prefixes.iter().any(|prefix| p.starts_with(prefix.as_str())))
+                .collect()
+        };
+
+        SnapshotDiff {
+            added: keep(self.added),
+            removed: keep(self.removed),
+            modified: keep(self.modified),
+        }
+    }
+
+    /// format the differences one path per line prefixed with "+", "-" or "M"
+    pub fn format_text(&self) -> String {
+        let mut res = String::new();
+
+        for (prefix, paths) in [
+            ("+", &self.added),
+            ("-", &self.removed),
+            ("M", &self.modified),
+        ] {
+            for path in paths {
+                res.push_str(&format!("{} {}\n", prefix, path));
+            }
+        }
+
+        res
+    }
+}
+
+/// compare two snapshots on the server
+pub fn diff_snapshots(
+    exec: &dyn Executor,
+    config: &Config,
+    old: &str,
+    new: &str,
+) -> Result<SnapshotDiff, SyncError> {
+    let snapshot_dir = Path::new(&config.snapshot);
+    let output = commands::compare_snapshots(
+        exec,
+        config.server(),
+        &snapshot_dir.join(check_snapshot_name(old)?),
+        &snapshot_dir.join(check_snapshot_name(new)?),
+    )?;
+
+    Ok(SnapshotDiff::parse_itemized(&output))
+}
+
+/// compare two snapshots using their manifests
+pub fn diff_manifests(
+    exec: &dyn Executor,
+    config: &Config,
+    old: &str,
+    new: &str,
+) -> Result<SnapshotDiff, SyncError> {
+    let snapshot_dir = Path::new(&config.snapshot);
+    let old = manifest::read_manifest(
+        exec,
+        config.server(),
+        &snapshot_dir.join(check_snapshot_name(old)?),
+    )?;
+    let new = manifest::read_manifest(
+        exec,
+        config.server(),
+        &snapshot_dir.join(check_snapshot_name(new)?),
+    )?;
+
+    Ok(old.diff(&new))
+}
+
+/// compare a snapshot with the current state of the source
+pub fn diff_source(
+    exec: &dyn Executor,
+    config: &Config,
+    snapshot: &str,
+) -> Result<SnapshotDiff, SyncError> {
+    let output = commands::compare_source(
+        exec,
+        &config.ssh_credentials,
+        config.mode,
+        &FilterRules::from_config(config),
+        &config.source,
+        &Path::new(&config.snapshot).join(check_snapshot_name(snapshot)?),
+    )?;
+
+    Ok(SnapshotDiff::parse_itemized(&output))
+}
+
+/// make sure the snapshot name does not point outside of the snapshot directory
+fn check_snapshot_name(name: &str) -> Result<&str, SyncError> {
+    if name.is_empty() || name.starts_with('/') || name.split('/').any(|c| c == "..") {
+        return Err(SyncError::InvalidSnapshotName(name.to_string()));
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+
+    #[test]
+    fn parse_itemized() {
+        let output = "*deleting   old.txt
+.d..t...... ./
+>f.st...... changed.txt
+>f+++++++++ new.txt
+cd+++++++++ new_dir/
+.f...p..... permissions.txt
+cL+++++++++ link -> target
+";
+
+        assert_eq!(
+            SnapshotDiff::parse_itemized(output),
+            SnapshotDiff {
+                added: vec![
+                    "new.txt".to_string(),
+                    "new_dir/".to_string(),
+                    "link".to_string()
+                ],
+                removed: vec!["old.txt".to_string()],
+                modified: vec!["changed.txt".to_string(), "permissions.txt".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let diff = SnapshotDiff {
+            added: vec!["etc/a".to_string(), "home/b".to_string()],
+            removed: vec!["home/c".to_string()],
+            modified: vec!["var/d".to_string()],
+        };
+
+        assert_eq!(
+            diff.filter(&["home/".to_string()]),
+            SnapshotDiff {
+                added: vec!["home/b".to_string()],
+                removed: vec!["home/c".to_string()],
+                modified: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(
+                args,
+                vec![
+                    "-l",
+                    "user",
+                    "-i",
+                    "id_file",
+                    "host",
+                    "rsync",
+                    "-ani",
+                    "--delete",
+                    "--exclude=/.backup-rsync/",
+                    "snapshot/2022-11-02T12:00:00Z_test/",
+                    "snapshot/2022-11-01T12:00:00Z_test/",
+                ]
+            );
+            Ok(">f+++++++++ new.txt\n".to_string())
+        });
+
+        let config = Config {
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            ..Default::default()
+        };
+        let res = super::diff_snapshots(
+            &mock,
+            &config,
+            "2022-11-01T12:00:00Z_test",
+            "2022-11-02T12:00:00Z_test",
+        )
+        .unwrap();
+
+        assert_eq!(res.added, vec!["new.txt".to_string()]);
+        assert!(super::diff_snapshots(&mock, &config, "../etc", "x").is_err());
+    }
+}
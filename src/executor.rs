@@ -0,0 +1,407 @@
+use crate::sync_error::SyncError;
+use exec_rs::Exec;
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// interval at which a running command is checked for timeouts and interrupts
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// time a command has to exit after SIGTERM before it is killed
+const TERMINATE_GRACE: Duration = Duration::from_secs(10);
+/// time the output of a command that has exited is waited for (e.g. if a child of the command keeps the pipes open)
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+/// number of lines of the error output included in the error of a failed command
+const STDERR_LINES: usize = 5;
+
+/// set when SIGINT or SIGTERM is received
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// options of a single command
+#[derive(Default)]
+pub struct ExecOptions<'a> {
+    /// the command is terminated if it runs longer
+    pub timeout: Option<Duration>,
+    /// called with every line of the output (lines end with "\n" or "\r", which is removed)
+    pub on_stdout: Option<&'a mut dyn FnMut(&str)>,
+    /// called with every line of the error output
+    pub on_stderr: Option<&'a mut dyn FnMut(&str)>,
+    /// exit statuses other than 0 that are not an error (e.g. rsync's 24 for files that vanished during the transfer)
+    pub allowed_statuses: &'a [i32],
+    /// set to the exit status of the command if it succeeds
+    pub exit_status: Option<&'a mut i32>,
+    /// written to the standard input of the command (which is empty otherwise)
+    pub stdin: Option<&'a [u8]>,
+}
+
+/// runs external commands; returns the output of the command
+pub trait Executor {
+    fn exec(&self, command: &str, args: &[&str]) -> Result<String, SyncError> {
+        self.exec_with(command, args, ExecOptions::default())
+    }
+
+    fn exec_with(
+        &self,
+        command: &str,
+        args: &[&str],
+        options: ExecOptions,
+    ) -> Result<String, SyncError>;
+}
+
+/// executors of exec-rs (e.g. its mocks) run the command to completion; the output is passed to the callbacks afterwards and the timeout is ignored
+impl<T: Exec + ?Sized> Executor for T {
+    fn exec_with(
+        &self,
+        command: &str,
+        args: &[&str],
+        mut options: ExecOptions,
+    ) -> Result<String, SyncError> {
+        let output = Exec::exec(self, command, args)?;
+
+        if let Some(on_stdout) = options.on_stdout.as_mut() {
+            split_lines(&output).for_each(|line| on_stdout(trim_line(line)));
+        }
+        if let Some(exit_status) = options.exit_status {
+            *exit_status = 0;
+        }
+
+        Ok(output)
+    }
+}
+
+/// runs the commands as child processes
+///
+/// Running commands are terminated on timeouts and when SIGINT or SIGTERM is received (see `handle_signals`). Commands started after an interrupt (e.g. hooks cleaning up) are not terminated by it; callers stop starting new steps using `check_interrupted`.
+pub struct ProcessExecutor {}
+
+impl Executor for ProcessExecutor {
+    fn exec_with(
+        &self,
+        command: &str,
+        args: &[&str],
+        mut options: ExecOptions,
+    ) -> Result<String, SyncError> {
+        // commands started after an interrupt are run to completion (a second signal exits immediately)
+        let interruptible = !interrupted();
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(match options.stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SyncError::CommandFailed(command.to_string(), e.to_string()))?;
+        let (sender, receiver) = mpsc::channel();
+
+        // written by a thread, as the command may fill the output pipes before reading all of its input
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), options.stdin) {
+            let input = input.to_vec();
+
+            thread::spawn(move || {
+                if let Err(e) = stdin.write_all(&input) {
+                    log::debug!("could not write the input of the command: {}", e);
+                }
+            });
+        }
+
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, Stream::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, Stream::Stderr, sender);
+        }
+
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let mut termination: Option<(SyncError, Instant)> = None;
+        let mut exited: Option<(ExitStatus, Instant)> = None;
+
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok((Stream::Stdout, line)) => {
+                    if let Some(on_stdout) = options.on_stdout.as_mut() {
+                        on_stdout(trim_line(&line));
+                    }
+                    stdout.push_str(&line);
+                }
+                Ok((Stream::Stderr, line)) => {
+                    if let Some(on_stderr) = options.on_stderr.as_mut() {
+                        on_stderr(trim_line(&line));
+                    }
+                    stderr.push_str(&line);
+                }
+                // both pipes are closed
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            match exited {
+                Some((_, at)) if at.elapsed() > OUTPUT_GRACE => break,
+                Some(_) => continue,
+                None => {}
+            }
+
+            if let Some(status) = child.try_wait()? {
+                exited = Some((status, Instant::now()));
+                continue;
+            }
+
+            match &termination {
+                None if interruptible && interrupted() => {
+                    log::warn!("interrupted; stopping \"{}\"", command);
+                    terminate(&child);
+                    termination = Some((SyncError::Interrupted, Instant::now()));
+                }
+                None if matches!(deadline, Some(deadline) if Instant::now() >= deadline) => {
+                    log::warn!("\"{}\" timed out; stopping it", command);
+                    terminate(&child);
+                    termination = Some((SyncError::Timeout(command.to_string()), Instant::now()));
+                }
+                Some((_, at)) if at.elapsed() > TERMINATE_GRACE => {
+                    log::warn!("\"{}\" did not stop; killing it", command);
+                    child.kill()?;
+                }
+                _ => {}
+            }
+        }
+
+        let status = match exited {
+            Some((status, _)) => status,
+            None => child.wait()?,
+        };
+
+        if let Some((e, _)) = termination {
+            return Err(e);
+        }
+
+        match status.code() {
+            Some(code) if code == 0 || options.allowed_statuses.contains(&code) => {
+                if let Some(exit_status) = options.exit_status {
+                    *exit_status = code;
+                }
+                Ok(stdout)
+            }
+            _ => Err(SyncError::CommandFailed(
+                command.to_string(),
+                describe_failure(status, &stderr),
+            )),
+        }
+    }
+}
+
+/// catch SIGINT and SIGTERM to stop the running command cleanly; a second signal exits immediately
+pub fn handle_signals() -> Result<(), SyncError> {
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        // safety: the handler only uses an atomic and `_exit`, which are async-signal-safe
+        unsafe {
+            signal_hook::low_level::register(signal, move || {
+                if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                    signal_hook::low_level::exit(128 + signal);
+                }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// whether SIGINT or SIGTERM was received
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// fail if SIGINT or SIGTERM was received; called before starting the next step of a run
+pub fn check_interrupted() -> Result<(), SyncError> {
+    match interrupted() {
+        true => Err(SyncError::Interrupted),
+        false => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// read the lines of a pipe in a thread and send them to the channel
+fn read_lines<R: Read + Send + 'static>(
+    mut pipe: R,
+    stream: Stream,
+    sender: Sender<(Stream, String)>,
+) {
+    thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        let mut pending = Vec::new();
+
+        loop {
+            let read = match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+
+            pending.extend_from_slice(&buffer[..read]);
+
+            // lines are only split at ASCII characters, so multi-byte characters remain intact
+            while let Some(end) = pending.iter().position(|b| *b == b'\n' || *b == b'\r') {
+                let line = pending.drain(..=end).collect::<Vec<u8>>();
+
+                if sender
+                    .send((stream, String::from_utf8_lossy(&line).to_string()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            let _ = sender.send((stream, String::from_utf8_lossy(&pending).to_string()));
+        }
+    });
+}
+
+/// ask the process to stop
+fn terminate(child: &Child) {
+    // safety: kill only sends a signal to the process, which has not been waited for and thus still exists
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+}
+
+/// describe the exit status with the last lines of the error output
+fn describe_failure(status: ExitStatus, stderr: &str) -> String {
+    let lines = split_lines(stderr)
+        .map(trim_line)
+        .filter(|l| !l.trim().is_empty())
+        .collect::<Vec<&str>>();
+    let tail = lines[lines.len().saturating_sub(STDERR_LINES)..].join("; ");
+
+    match tail.is_empty() {
+        true => status.to_string(),
+        false => format!("{}: {}", status, tail),
+    }
+}
+
+/// split the output into lines ending with "\n" or "\r"
+fn split_lines(output: &str) -> impl Iterator<Item = &str> {
+    output
+        .split_inclusive(['\n', '\r'])
+        .filter(|line| !line.is_empty())
+}
+
+fn trim_line(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_executor() {
+        let mut lines = Vec::new();
+        let mut on_stdout = |line: &str| lines.push(line.to_string());
+        let output = ProcessExecutor {}
+            .exec_with(
+                "sh",
+                &["-c", "printf 'a\\rb\\nc'"],
+                ExecOptions {
+                    on_stdout: Some(&mut on_stdout),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(output, "a\rb\nc");
+        assert_eq!(lines, ["a", "b", "c"]);
+
+        let res = ProcessExecutor {}.exec("sh", &["-c", "echo failed >&2; exit 3"]);
+
+        assert!(
+            matches!(&res, Err(SyncError::CommandFailed(command, message)) if command == "sh" && message.ends_with("status: 3: failed")),
+            "{:?}",
+            res
+        );
+
+        let output = ProcessExecutor {}
+            .exec_with(
+                "tr",
+                &["a", "b"],
+                ExecOptions {
+                    stdin: Some(b"aaa"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(output, "bbb");
+
+        let mut exit_status = 0;
+        let res = ProcessExecutor {}.exec_with(
+            "sh",
+            &["-c", "exit 24"],
+            ExecOptions {
+                allowed_statuses: &[24],
+                exit_status: Some(&mut exit_status),
+                ..Default::default()
+            },
+        );
+
+        assert!(res.is_ok());
+        assert_eq!(exit_status, 24);
+    }
+
+    #[test]
+    fn timeout() {
+        let start = Instant::now();
+        let res = ProcessExecutor {}.exec_with(
+            "sleep",
+            &["10"],
+            ExecOptions {
+                timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        );
+
+        assert!(matches!(res, Err(SyncError::Timeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn mock_executor() {
+        let mut mock = exec_rs::MockExec::new();
+        let mut lines = Vec::new();
+        let mut on_stdout = |line: &str| lines.push(line.to_string());
+
+        mock.expect_exec()
+            .once()
+            .returning(|_, _| Ok("a\nb\n".to_string()));
+
+        let executor: &dyn Executor = &mock;
+
+        assert_eq!(
+            executor
+                .exec_with(
+                    "ls",
+                    &[],
+                    ExecOptions {
+                        on_stdout: Some(&mut on_stdout),
+                        ..Default::default()
+                    }
+                )
+                .unwrap(),
+            "a\nb\n"
+        );
+        assert_eq!(lines, ["a", "b"]);
+    }
+}
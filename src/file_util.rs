@@ -0,0 +1,123 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+/// number of names tried before creating a temporary file fails (the names in the shared temporary directory are predictable)
+const TEMP_FILE_ATTEMPTS: usize = 100;
+
+/// temporary file that is removed when dropped
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// create a temporary file with the given content, readable only by the owner
+    ///
+    /// An existing file or symlink is never opened, so that content like credentials cannot be redirected to another file.
+    pub fn new(prefix: &str, content: &[u8]) -> std::io::Result<Self> {
+        let mut attempts = 0;
+
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "{}-{}-{}",
+                prefix,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let mut file = match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && attempts < TEMP_FILE_ATTEMPTS => {
+                    attempts += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            // removed if writing fails
+            let temp_file = Self { path };
+
+            file.write_all(content)?;
+
+            return Ok(temp_file);
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!(
+                "could not remove temporary file \"{}\": {}",
+                self.path.to_string_lossy(),
+                e
+            );
+        }
+    }
+}
+
+/// write a file by writing to a temporary file in the same directory and renaming it
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut file = fs::File::create(&tmp_path)?;
+
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn temp_file() {
+        let path = {
+            let file = TempFile::new("backup-rsync-test", b"content").unwrap();
+
+            assert_eq!(fs::read(file.path()).unwrap(), b"content");
+            assert_eq!(
+                fs::metadata(file.path()).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+            file.path().to_path_buf()
+        };
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn write_atomic() {
+        let dir =
+            std::env::temp_dir().join(format!("backup-rsync-write-atomic-{}", std::process::id()));
+        let path = dir.join("file.txt");
+
+        super::write_atomic(&path, b"first").unwrap();
+        super::write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{commands, config::Config, executor::Executor, sync_error::SyncError};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// include and exclude rules applied to the source
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FilterRules {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub exclude_file: Option<PathBuf>,
+}
+
+impl FilterRules {
+    /// get the rules configured for the job
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            exclude_file: config.exclude_file.as_ref().map(PathBuf::from),
+        }
+    }
+
+    /// rsync arguments for the rules
+    ///
+    /// rsync uses the first matching rule, so the inline includes take precedence over the inline excludes, which take precedence over the rules of the exclude file.
+    pub fn args(&self) -> Result<Vec<String>, SyncError> {
+        let mut args = self
+            .include
+            .iter()
+            .map(|p| format!("--include={}", p))
+            .chain(self.exclude.iter().map(|p| format!("--exclude={}", p)))
+            .collect::<Vec<String>>();
+
+        if let Some(exclude_file) = &self.exclude_file {
+            args.push(format!(
+                "--exclude-from={}",
+                exclude_file
+                    .to_str()
+                    .ok_or_else(|| SyncError::PathConversionError("exclude file".to_string()))?
+            ));
+        }
+
+        Ok(args)
+    }
+}
+
+/// file or directory of the source excluded by a rule
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ExcludedEntry {
+    /// path relative to the source (absolute for multiple sources, without the leading slash)
+    pub path: String,
+    pub directory: bool,
+    pub pattern: String,
+}
+
+impl ExcludedEntry {
+    /// parse the output of `rsync --debug=FILTER1`; only the topmost excluded entries are reported, as rsync does not descend into excluded directories
+    pub fn parse(output: &str) -> Vec<ExcludedEntry> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let (_, entry) = line.split_once("hiding ")?;
+                let (kind, entry) = entry.split_once(' ')?;
+                let (path, pattern) = entry.rsplit_once(" because of pattern ")?;
+
+                Some(ExcludedEntry {
+                    path: path.to_string(),
+                    directory: kind == "directory",
+                    pattern: pattern.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// get the files and directories below the source that are excluded by the configured rules
+pub fn excluded(exec: &dyn Executor, config: &Config) -> Result<Vec<ExcludedEntry>, SyncError> {
+    let output = commands::list_excluded(
+        exec,
+        &config.ssh_credentials,
+        config.mode,
+        &FilterRules::from_config(config),
+        &config.source,
+    )?;
+
+    Ok(ExcludedEntry::parse(&output))
+}
+
+/// format the excluded entries as text
+pub fn format_text(entries: &[ExcludedEntry]) -> String {
+    entries
+        .iter()
+        .map(|e| {
+            format!(
+                "{}{}  ({})\n",
+                e.path,
+                if e.directory { "/" } else { "" },
+                e.pattern
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args() {
+        let rules = FilterRules {
+            include: vec!["/home/user/.cache/important/".to_string()],
+            exclude: vec!["/home/*/.cache/".to_string(), "*.tmp".to_string()],
+            exclude_file: Some(PathBuf::from("exclude_file")),
+        };
+
+        assert_eq!(
+            rules.args().unwrap(),
+            vec![
+                "--include=/home/user/.cache/important/",
+                "--exclude=/home/*/.cache/",
+                "--exclude=*.tmp",
+                "--exclude-from=exclude_file",
+            ]
+        );
+        assert!(FilterRules::default().args().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse() {
+        let output = "sending incremental file list
+[sender] hiding directory home/user/.cache because of pattern /home/*/.cache/
+[sender] showing file home/user/keep.tmp because of pattern keep.tmp
+[sender] hiding file home/user/notes.tmp because of pattern *.tmp
+drwxr-xr-x          4,096 2022/11/01 12:00:00 home
+";
+
+        assert_eq!(
+            ExcludedEntry::parse(output),
+            vec![
+                ExcludedEntry {
+                    path: "home/user/.cache".to_string(),
+                    directory: true,
+                    pattern: "/home/*/.cache/".to_string(),
+                },
+                ExcludedEntry {
+                    path: "home/user/notes.tmp".to_string(),
+                    directory: false,
+                    pattern: "*.tmp".to_string(),
+                },
+            ]
+        );
+    }
+}
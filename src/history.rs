@@ -0,0 +1,153 @@
+use crate::{
+    clock::SnapshotTimeZone,
+    list::{self, format_rows},
+    outcome::{RunOutcome, RunStatus},
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Utc};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+/// file in the state directory recording every run of the jobs sharing the directory (one JSON object per line)
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// append the outcome of a run to the history
+pub fn append(path: &Path, outcome: &RunOutcome) -> Result<(), SyncError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut line = serde_json::to_vec(outcome)?;
+
+    line.push(b'\n');
+    // a single write in append mode does not interleave with other runs
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)?;
+
+    Ok(())
+}
+
+/// read the runs of a job from the history (oldest first); a missing file yields an empty history
+pub fn read(path: &Path, job: &str) -> Result<Vec<RunOutcome>, SyncError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<RunOutcome>(line) {
+            Ok(outcome) => Some(outcome),
+            Err(e) => {
+                // e.g. a line truncated by a crash
+                log::warn!("ignoring invalid history entry: {}", e);
+                None
+            }
+        })
+        .filter(|outcome| outcome.job == job)
+        .collect())
+}
+
+/// get the last run, optionally only considering successful runs
+pub fn last(history: &[RunOutcome], success: bool) -> Option<&RunOutcome> {
+    history
+        .iter()
+        .filter(|o| !success || o.status == RunStatus::Success)
+        .max_by_key(|o| o.start)
+}
+
+/// format the runs as a table
+pub fn format_table(history: &[RunOutcome], time_zone: SnapshotTimeZone) -> String {
+    let date = |d: &DateTime<Utc>| {
+        time_zone
+            .convert(d)
+            .format("%Y-%m-%d %H:%M %:z")
+            .to_string()
+    };
+    let mut rows = vec![vec![
+        "START".to_string(),
+        "DURATION".to_string(),
+        "STATUS".to_string(),
+        "TRANSFERRED".to_string(),
+        "SNAPSHOT".to_string(),
+    ]];
+
+    rows.extend(history.iter().map(|o| {
+        vec![
+            date(&o.start),
+            list::format_age(o.end - o.start),
+            o.status.name().to_string(),
+            o.stats
+                .as_ref()
+                .and_then(|s| s.transferred_file_size)
+                .map(list::format_size)
+                .unwrap_or_default(),
+            o.snapshot
+                .clone()
+                .or_else(|| o.error.clone())
+                .unwrap_or_default(),
+        ]
+    }));
+
+    format_rows(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn append_and_read() {
+        let path = std::env::temp_dir().join(format!(
+            "backup-rsync-history-{}/{}",
+            std::process::id(),
+            HISTORY_FILE
+        ));
+
+        assert!(read(&path, "job").unwrap().is_empty());
+
+        let success = RunOutcome {
+            status: RunStatus::Success,
+            end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+            snapshot: Some("snapshot/2022-11-01T12:00:00Z_job".to_string()),
+            ..RunOutcome::new("job", Utc.ymd(2022, 11, 1).and_hms(12, 0, 0))
+        };
+        let failure = RunOutcome {
+            error: Some("error".to_string()),
+            ..RunOutcome::new("job", Utc.ymd(2022, 11, 2).and_hms(12, 0, 0))
+        };
+
+        append(&path, &success).unwrap();
+        append(&path, &RunOutcome::new("other", Utc::now())).unwrap();
+        append(&path, &failure).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"job\": \"jo")
+            .unwrap();
+
+        let history = read(&path, "job").unwrap();
+
+        assert_eq!(history, vec![success.clone(), failure.clone()]);
+        assert_eq!(last(&history, false), Some(&failure));
+        assert_eq!(last(&history, true), Some(&success));
+        assert_eq!(
+            format_table(&history, SnapshotTimeZone::Utc),
+            "START                    DURATION  STATUS   TRANSFERRED  SNAPSHOT
+2022-11-01 12:00 +00:00  5m        success               snapshot/2022-11-01T12:00:00Z_job
+2022-11-02 12:00 +00:00  0m        failure               error
+"
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+use crate::custom_duration::CustomDuration;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// commands executed at specific points of a backup run
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+pub struct Hooks {
+    pub pre_sync: Option<Hook>,
+    pub post_sync: Option<Hook>,
+    pub pre_snapshot: Option<Hook>,
+    pub post_prune: Option<Hook>,
+    pub on_failure: Option<Hook>,
+}
+
+impl Hooks {
+    /// get the hook configured for a stage
+    pub fn get(&self, stage: HookStage) -> Option<&Hook> {
+        match stage {
+            HookStage::PreSync => self.pre_sync.as_ref(),
+            HookStage::PostSync => self.post_sync.as_ref(),
+            HookStage::PreSnapshot => self.pre_snapshot.as_ref(),
+            HookStage::PostPrune => self.post_prune.as_ref(),
+            HookStage::OnFailure => self.on_failure.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Hook {
+    /// command line passed to `sh -c`
+    pub command: String,
+    pub timeout: Option<CustomDuration>,
+    #[serde(default)]
+    pub on_error: HookFailurePolicy,
+}
+
+/// what to do if a hook fails or times out
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    #[default]
+    Abort,
+    Continue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    PreSync,
+    PostSync,
+    PreSnapshot,
+    PostPrune,
+    OnFailure,
+}
+
+impl HookStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookStage::PreSync => "pre_sync",
+            HookStage::PostSync => "post_sync",
+            HookStage::PreSnapshot => "pre_snapshot",
+            HookStage::PostPrune => "post_prune",
+            HookStage::OnFailure => "on_failure",
+        }
+    }
+}
+
+/// information about the run passed to the hook as environment variables
+pub struct HookContext<'a> {
+    pub job: &'a str,
+    pub snapshot: &'a str,
+    pub status: &'a str,
+}
@@ -1,6 +1,36 @@
+pub mod bandwidth;
+pub mod check;
+pub mod cli;
+pub mod clock;
 pub mod commands;
 pub mod config;
+pub mod config_file;
 pub mod custom_duration;
+pub mod daemon;
+pub mod diff;
+pub mod executor;
+pub mod file_util;
+pub mod filter;
+pub mod history;
+pub mod hooks;
+pub mod hostname;
+pub mod list;
+pub mod manifest;
+pub mod metadata;
+pub mod metrics;
+pub mod naming;
+pub mod notification;
+pub mod outcome;
+pub mod progress;
+pub mod retention;
+pub mod rsync_options;
+pub mod rsync_stats;
+pub mod schedule;
+pub mod secret;
+pub mod source;
 pub mod ssh_credentials;
+pub mod state;
 pub mod sync;
 pub mod sync_error;
+pub mod systemd;
+pub mod verify;
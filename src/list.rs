@@ -0,0 +1,312 @@
+use crate::{
+    clock::SnapshotTimeZone,
+    commands,
+    config::Config,
+    executor::Executor,
+    metadata::{self, SnapshotMetadata},
+    naming::NamingScheme,
+    retention,
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
+
+/// information about a snapshot on the server
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub date: DateTime<Utc>,
+    pub age_seconds: i64,
+    /// retention rule keeping the snapshot; `None` if the snapshot will be pruned by the next run
+    pub kept_by: Option<String>,
+    pub pinned: bool,
+    pub apparent_size: Option<u64>,
+    pub unique_size: Option<u64>,
+    /// metadata stored in the snapshot (if any)
+    pub metadata: Option<SnapshotMetadata>,
+}
+
+/// get all snapshots (newest first) with their retention status and optionally their disk usage
+pub fn list_snapshots(
+    exec: &dyn Executor,
+    config: &Config,
+    date_time: &DateTime<Utc>,
+    with_usage: bool,
+) -> Result<Vec<SnapshotInfo>, SyncError> {
+    let mut snapshots = commands::get_snapshots(
+        exec,
+        config.server(),
+        Path::new(&config.snapshot),
+        &NamingScheme::from_config(config)?,
+    )?;
+
+    snapshots.sort_by_key(|(date, _)| Reverse(*date));
+
+    let paths = snapshots
+        .iter()
+        .map(|(_, name)| Path::new(&config.snapshot).join(name))
+        .collect::<Vec<PathBuf>>();
+    let paths = paths.iter().map(|p| p.as_path()).collect::<Vec<&Path>>();
+    let snapshot_metadata = metadata::read_metadata(exec, config.server(), &paths)?;
+    let to_be_deleted =
+        retention::snapshots_to_prune(config, date_time, &snapshots, &snapshot_metadata)?;
+    let usage = match with_usage {
+        true => Some(commands::get_disk_usage(exec, config.server(), &paths)?),
+        false => None,
+    };
+
+    snapshots
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (date, name))| {
+            let age = *date_time - date;
+            let pinned = config.pinned.contains(&name);
+            let kept_by = if pinned {
+                Some("pinned".to_string())
+            } else if let Some(job) = retention::other_job(config, snapshot_metadata[idx].as_ref())
+            {
+                Some(format!("job {}", job))
+            } else if to_be_deleted.contains(&name) {
+                None
+            } else {
+                retention::retention_rule(&config.policy, age)?.map(|cd| cd.to_string())
+            };
+
+            Ok(SnapshotInfo {
+                age_seconds: age.num_seconds(),
+                kept_by,
+                pinned,
+                apparent_size: usage.as_ref().map(|u| u[idx].0),
+                unique_size: usage.as_ref().map(|u| u[idx].1),
+                metadata: snapshot_metadata[idx].clone(),
+                name,
+                date,
+            })
+        })
+        .collect()
+}
+
+/// format the snapshots as a table with the dates in the given time zone
+pub fn format_table(snapshots: &[SnapshotInfo], time_zone: SnapshotTimeZone) -> String {
+    let with_usage = snapshots.iter().any(|s| s.apparent_size.is_some());
+    let with_host = snapshots.iter().any(|s| s.metadata.is_some());
+    let mut rows = vec![vec![
+        "NAME".to_string(),
+        "DATE".to_string(),
+        "AGE".to_string(),
+        "KEPT BY".to_string(),
+        "PINNED".to_string(),
+    ]];
+
+    if with_host {
+        rows[0].push("HOST".to_string());
+    }
+
+    if with_usage {
+        rows[0].push("APPARENT".to_string());
+        rows[0].push("UNIQUE".to_string());
+    }
+
+    for snapshot in snapshots {
+        let mut row = vec![
+            snapshot.name.clone(),
+            time_zone
+                .convert(&snapshot.date)
+                .format("%Y-%m-%d %H:%M %:z")
+                .to_string(),
+            format_age(Duration::seconds(snapshot.age_seconds)),
+            snapshot
+                .kept_by
+                .clone()
+                .unwrap_or_else(|| "(prune)".to_string()),
+            match snapshot.pinned {
+                true => "yes".to_string(),
+                false => "no".to_string(),
+            },
+        ];
+
+        if with_host {
+            row.push(
+                snapshot
+                    .metadata
+                    .as_ref()
+                    .map(|m| m.source_host.clone())
+                    .unwrap_or_default(),
+            );
+        }
+
+        if with_usage {
+            row.push(snapshot.apparent_size.map(format_size).unwrap_or_default());
+            row.push(snapshot.unique_size.map(format_size).unwrap_or_default());
+        }
+
+        rows.push(row);
+    }
+
+    format_rows(&rows)
+}
+
+/// align the columns of a table
+pub fn format_rows(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|col| {
+            rows.iter()
+                .filter_map(|r| r.get(col))
+                .map(|c| c.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<usize>>();
+
+    rows.iter()
+        .map(|row| {
+            let mut line = row
+                .iter()
+                .enumerate()
+                .map(|(col, cell)| format!("{:width$}", cell, width = widths[col]))
+                .collect::<Vec<String>>()
+                .join("  ");
+
+            line.truncate(line.trim_end().len());
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+/// format a duration in a compact human readable form (e.g. "2d 3h")
+pub fn format_age(age: Duration) -> String {
+    let minutes = age.num_minutes();
+
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else if minutes < 60 * 24 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}d {}h", minutes / (60 * 24), (minutes / 60) % 24)
+    }
+}
+
+/// format a number of bytes using binary prefixes
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{custom_duration::CustomDuration, ssh_credentials::SshCredentials};
+    use chrono::TimeZone;
+
+    #[test]
+    fn list_snapshots() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..], ["ls", "-A1", "snapshot"]);
+            Ok("2022-11-01T12:00:00Z_test\n2022-11-02T12:00:00Z_test\n".to_string())
+        });
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..7], ["sh", "-c"]);
+            assert!(args[7].contains("metadata.json"));
+            Ok("\n\n".to_string())
+        });
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..7], ["sh", "-c"]);
+            assert!(args[7]
+                .contains("snapshot/2022-11-02T12:00:00Z_test snapshot/2022-11-01T12:00:00Z_test"));
+            Ok("4096\t1024\n4096\t512\n".to_string())
+        });
+
+        let config = Config {
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test".to_string(),
+            policy: vec![CustomDuration::days(2), CustomDuration::weeks(1)],
+            pinned: vec!["2022-11-01T12:00:00Z_test".to_string()],
+            ..Default::default()
+        };
+        let res = super::list_snapshots(
+            &mock,
+            &config,
+            &Utc.ymd(2022, 11, 2).and_hms(13, 0, 0),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            vec![
+                SnapshotInfo {
+                    name: "2022-11-02T12:00:00Z_test".to_string(),
+                    date: Utc.ymd(2022, 11, 2).and_hms(12, 0, 0),
+                    age_seconds: 3600,
+                    kept_by: Some("2d".to_string()),
+                    pinned: false,
+                    apparent_size: Some(4096),
+                    unique_size: Some(1024),
+                    metadata: None,
+                },
+                SnapshotInfo {
+                    name: "2022-11-01T12:00:00Z_test".to_string(),
+                    date: Utc.ymd(2022, 11, 1).and_hms(12, 0, 0),
+                    age_seconds: 25 * 3600,
+                    kept_by: Some("pinned".to_string()),
+                    pinned: true,
+                    apparent_size: Some(4096),
+                    unique_size: Some(512),
+                    metadata: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn format_table() {
+        let snapshots = [SnapshotInfo {
+            name: "2022-11-02T12:00:00Z_test".to_string(),
+            date: Utc.ymd(2022, 11, 2).and_hms(12, 0, 0),
+            age_seconds: 26 * 3600 + 60,
+            kept_by: None,
+            pinned: false,
+            apparent_size: None,
+            unique_size: None,
+            metadata: None,
+        }];
+
+        assert_eq!(
+            super::format_table(&snapshots, SnapshotTimeZone::Utc),
+            "NAME                       DATE                     AGE    KEPT BY  PINNED\n\
+             2022-11-02T12:00:00Z_test  2022-11-02 12:00 +00:00  1d 2h  (prune)  no\n"
+        );
+        assert!(super::format_table(
+            &snapshots,
+            SnapshotTimeZone::Fixed(chrono::FixedOffset::west(3600))
+        )
+        .contains("2022-11-02 11:00 -01:00"));
+    }
+
+    #[test]
+    fn format_size() {
+        assert_eq!(super::format_size(512), "512 B");
+        assert_eq!(super::format_size(1536), "1.5 KiB");
+        assert_eq!(super::format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
@@ -1,16 +1,298 @@
 use anyhow::Context;
-use backup_rsync::{config::Config, sync::Sync};
-use std::path::Path;
+use backup_rsync::{
+    check::{self, CheckResult},
+    cli::{self, Command},
+    config::Config,
+    config_file, daemon, diff,
+    executor::{self, ProcessExecutor},
+    filter, history, list, naming,
+    state::JobState,
+    sync::Sync,
+    systemd, verify,
+};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
 
 fn main() -> anyhow::Result<()> {
     // init logger
     env_logger::init();
     // get parameters
-    let config_path = std::env::var("BACK_UP_RSYNC_CONFIG")?;
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let command = cli::parse(&args)?;
+
+    // stop running commands cleanly on Ctrl+C and when the service is stopped
+    executor::handle_signals()?;
+
+    if command == Command::Help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    if command == Command::Schema {
+        let schema = config_file::schema()?;
+
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(());
+    }
+
+    let config_path = std::env::var("BACK_UP_RSYNC_CONFIG");
+
+    // old configuration files may not be readable as a whole (e.g. missing base configurations)
+    if let Command::MigrateConfig { configs, dry_run } = &command {
+        let paths = match configs.is_empty() {
+            true => vec![PathBuf::from(config_path?)],
+            false => configs.iter().map(PathBuf::from).collect(),
+        };
+
+        for path in paths {
+            match config_file::migrate_file(&path, *dry_run)? {
+                Some(migrated) if *dry_run => print!("{}", migrated),
+                Some(_) => println!(
+                    "{}: upgraded to version {}",
+                    path.to_string_lossy(),
+                    config_file::CONFIG_VERSION
+                ),
+                None => println!("{}: up to date", path.to_string_lossy()),
+            }
+        }
+
+        return Ok(());
+    }
+
+    // the daemon reads the configuration of each job itself
+    if let Command::Daemon { configs } = &command {
+        let paths = match configs.is_empty() {
+            true => vec![PathBuf::from(config_path?)],
+            false => configs.iter().map(PathBuf::from).collect(),
+        };
+
+        return daemon::run(&paths);
+    }
+
+    // monitoring systems expect a status even if the configuration cannot be read or the server cannot be reached
+    if let Command::Check { json } = command {
+        let res = match &config_path {
+            Ok(path) => match Config::read_from_file(Path::new(path)) {
+                Ok(config) => check::check(&ProcessExecutor {}, &config, &Utc::now())
+                    .unwrap_or_else(|e| {
+                        CheckResult::unknown(&format!("error checking the snapshots: {}", e))
+                    }),
+                Err(e) => CheckResult::unknown(&format!("could not read config file: {:#}", e)),
+            },
+            Err(e) => CheckResult::unknown(&format!("BACK_UP_RSYNC_CONFIG: {}", e)),
+        };
+
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&res)?),
+            false => print!("{}", res.format_text()),
+        }
+
+        std::process::exit(res.status.exit_code());
+    }
+
+    let config_path = config_path?;
     let config =
         Config::read_from_file(Path::new(&config_path)).context("could not read config file")?;
-    // create sync object
-    let sync = Sync::new(config);
 
-    sync.execute().context("error executing the sync")
+    match command {
+        Command::Run => {
+            // create sync object; the progress is shown when run manually
+            let sync = Sync::new(config).with_progress(true);
+
+            sync.execute().context("error executing the sync")
+        }
+        Command::List {
+            json,
+            usage,
+            unrecognized: true,
+            ..
+        } => {
+            let entries = naming::unrecognized_entries(&ProcessExecutor {}, &config)
+                .context("error listing the snapshot directory")?;
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&entries)?),
+                false => entries.iter().for_each(|e| println!("{}", e)),
+            }
+
+            if usage {
+                log::warn!("--usage is ignored for unrecognized entries");
+            }
+
+            Ok(())
+        }
+        Command::List {
+            json,
+            usage,
+            time_zone,
+            ..
+        } => {
+            let snapshots = list::list_snapshots(&ProcessExecutor {}, &config, &Utc::now(), usage)
+                .context("error listing the snapshots")?;
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&snapshots)?),
+                false => print!(
+                    "{}",
+                    list::format_table(&snapshots, time_zone.unwrap_or(config.time_zone))
+                ),
+            }
+
+            Ok(())
+        }
+        Command::Diff {
+            old,
+            new,
+            paths,
+            manifest,
+            json,
+        } => {
+            let exec = ProcessExecutor {};
+            let res = match (new, manifest) {
+                (Some(new), true) => diff::diff_manifests(&exec, &config, &old, &new),
+                (Some(new), false) => diff::diff_snapshots(&exec, &config, &old, &new),
+                (None, _) => diff::diff_source(&exec, &config, &old),
+            }
+            .context("error comparing the snapshots")?
+            .filter(&paths);
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&res)?),
+                false => print!("{}", res.format_text()),
+            }
+
+            Ok(())
+        }
+        Command::Verify {
+            sample,
+            manifest,
+            json,
+        } => {
+            let exec = ProcessExecutor {};
+            let report = match manifest {
+                true => verify::verify_manifest(&exec, &config),
+                false => verify::verify(&exec, &config, sample),
+            }
+            .context("error verifying the snapshot")?;
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&report)?),
+                false => print!("{}", report.format_text()),
+            }
+
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+
+            Ok(())
+        }
+        Command::Excluded { json } => {
+            let entries = filter::excluded(&ProcessExecutor {}, &config)
+                .context("error listing the excluded files")?;
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&entries)?),
+                false => print!("{}", filter::format_text(&entries)),
+            }
+
+            Ok(())
+        }
+        Command::MigrateNames { from, dry_run } => {
+            let renames = naming::migrate_names(
+                &ProcessExecutor {},
+                &config,
+                from.as_deref().unwrap_or(naming::DEFAULT_TEMPLATE),
+                dry_run,
+            )
+            .context("error renaming the snapshots")?;
+
+            for (old, new) in renames {
+                println!("{} -> {}", old, new);
+            }
+
+            Ok(())
+        }
+        Command::Systemd { user, output } => {
+            let config_path = std::fs::canonicalize(&config_path)
+                .context("could not resolve the path of the config file")?;
+            let units = systemd::units(&config, &config_path, &std::env::current_exe()?, user)
+                .context("error generating the systemd units")?;
+
+            match output {
+                Some(dir) => {
+                    for (extension, content) in
+                        [("service", &units.service), ("timer", &units.timer)]
+                    {
+                        let path = Path::new(&dir).join(format!("{}.{}", units.name, extension));
+
+                        std::fs::write(&path, content)
+                            .context(format!("could not write \"{}\"", path.to_string_lossy()))?;
+                        println!("{}", path.to_string_lossy());
+                    }
+                }
+                None => print!(
+                    "# {0}.service\n{1}\n# {0}.timer\n{2}",
+                    units.name, units.service, units.timer
+                ),
+            }
+
+            Ok(())
+        }
+        Command::Status { json } => {
+            let state_file = config
+                .state_file()
+                .context("no state directory configured")?;
+            let state = JobState::read(&state_file).context("error reading the job state")?;
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&state)?),
+                false => print!("{}", state.format_text(config.time_zone)),
+            }
+
+            Ok(())
+        }
+        Command::History { job, limit, json } => {
+            let history_file = config
+                .history_file()
+                .context("no state directory configured")?;
+            let mut runs =
+                history::read(&history_file, job.as_deref().unwrap_or(config.job_name()))
+                    .context("error reading the history")?;
+
+            if let Some(limit) = limit {
+                runs.drain(..runs.len().saturating_sub(limit));
+            }
+
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&runs)?),
+                false => print!("{}", history::format_table(&runs, config.time_zone)),
+            }
+
+            Ok(())
+        }
+        Command::Last { job, success, json } => {
+            let history_file = config
+                .history_file()
+                .context("no state directory configured")?;
+            let runs = history::read(&history_file, job.as_deref().unwrap_or(config.job_name()))
+                .context("error reading the history")?;
+            let last = history::last(&runs, success);
+
+            match (json, last) {
+                (true, _) => println!("{}", serde_json::to_string_pretty(&last)?),
+                (false, Some(last)) => print!(
+                    "{}",
+                    history::format_table(std::slice::from_ref(last), config.time_zone)
+                ),
+                (false, None) => println!("no run recorded"),
+            }
+
+            Ok(())
+        }
+        Command::Check { .. }
+        | Command::Daemon { .. }
+        | Command::Schema
+        | Command::MigrateConfig { .. }
+        | Command::Help => Ok(()),
+    }
 }
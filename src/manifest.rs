@@ -0,0 +1,704 @@
+use crate::{
+    commands::{self, Server},
+    diff::SnapshotDiff,
+    executor::{ExecOptions, Executor},
+    file_util::{self, TempFile},
+    sync_error::SyncError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// directory inside each snapshot holding the files written by this tool
+pub const METADATA_DIR: &str = ".backup-rsync";
+const MANIFEST_FILE: &str = "manifest.tsv";
+/// header of the manifest; since version 2, "\", tabs, newlines and carriage returns in paths are escaped
+const HEADER: &str = "# backup-rsync manifest v2: path, size, mtime, mode, sha256";
+/// header of manifests with unescaped paths
+const HEADER_V1: &str = "# backup-rsync manifest v1:";
+
+/// file attributes as listed by find
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub path: String,
+    pub size: u64,
+    pub mtime: String,
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub file: FileInfo,
+    pub hash: String,
+}
+
+/// list of all files of a snapshot with their content hash
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn parse(content: &str) -> Result<Self, SyncError> {
+        let mut entries = BTreeMap::new();
+        let escaped = !content.starts_with(HEADER_V1);
+
+        for line in content.lines().filter(|l| !l.starts_with('#')) {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+
+            if fields.len() != 5 {
+                return Err(SyncError::UnexpectedOutput(line.to_string()));
+            }
+
+            let path = match escaped {
+                true => unescape(fields[0]),
+                false => fields[0].to_string(),
+            };
+
+            entries.insert(
+                path.clone(),
+                ManifestEntry {
+                    file: FileInfo {
+                        path,
+                        size: fields[1].parse()?,
+                        mtime: fields[2].to_string(),
+                        mode: fields[3].to_string(),
+                    },
+                    hash: fields[4].to_string(),
+                },
+            );
+        }
+
+        Ok(Manifest { entries })
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut res = format!("{}\n", HEADER);
+
+        for entry in self.entries.values() {
+            res.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                escape(&entry.file.path),
+                entry.file.size,
+                entry.file.mtime,
+                entry.file.mode,
+                entry.hash
+            ));
+        }
+
+        res
+    }
+
+    /// compare with the manifest of a newer snapshot
+    pub fn diff(&self, newer: &Manifest) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+
+        for (path, entry) in &newer.entries {
+            match self.entries.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(old) if old != entry => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+
+        diff.removed = self
+            .entries
+            .keys()
+            .filter(|path| !newer.entries.contains_key(*path))
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
+
+/// parse the output of `find -printf "%P\0%s\0%T@\0%m\0"` (NUL-separated, as paths may contain any other character); the metadata directory is skipped
+pub fn parse_file_list(output: &str) -> Result<Vec<FileInfo>, SyncError> {
+    let fields = output.split_terminator('\0').collect::<Vec<&str>>();
+
+    if fields.len() % 4 != 0 {
+        return Err(SyncError::UnexpectedOutput(output.replace('\0', "\t")));
+    }
+
+    fields
+        .chunks(4)
+        .filter(|f| !f[0].starts_with(&format!("{}/", METADATA_DIR)))
+        .map(|f| {
+            Ok(FileInfo {
+                path: f[0].to_string(),
+                size: f[1].parse()?,
+                mtime: f[2].to_string(),
+                mode: f[3].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// parse the output of sha256sum into a map from path to hash
+pub fn parse_hashes(output: &str) -> Result<BTreeMap<String, String>, SyncError> {
+    output
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            // sha256sum escapes file names containing "\", a newline or a carriage return and prefixes the line with "\"
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let (hash, path) = line
+                .split_once("  ")
+                .ok_or_else(|| SyncError::UnexpectedOutput(line.to_string()))?;
+            let path = match escaped {
+                true => unescape(path),
+                false => path.to_string(),
+            };
+
+            Ok((path, hash.to_string()))
+        })
+        .collect()
+}
+
+/// escape "\", tabs, newlines and carriage returns
+fn escape(path: &str) -> String {
+    let mut res = String::with_capacity(path.len());
+
+    for c in path.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            c => res.push(c),
+        }
+    }
+
+    res
+}
+
+/// reverse `escape` (also used for the file names escaped by sha256sum); unknown escapes are kept
+fn unescape(path: &str) -> String {
+    let mut res = String::with_capacity(path.len());
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('\\') => res.push('\\'),
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some(other) => {
+                res.push('\\');
+                res.push(other);
+            }
+            None => res.push('\\'),
+        }
+    }
+
+    res
+}
+
+/// get the path of the manifest of a snapshot
+pub fn manifest_path(snapshot_path: &Path) -> PathBuf {
+    snapshot_path.join(METADATA_DIR).join(MANIFEST_FILE)
+}
+
+/// read the manifest of a snapshot from the server
+/// SHA-256 hashes of the manifests by snapshot name
+///
+/// The manifests are stored inside the snapshots and could be rewritten together with the files they describe; the hashes are kept outside the snapshots (in the state directory), so that such a change is detected.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ManifestHashes(pub BTreeMap<String, String>);
+
+impl ManifestHashes {
+    /// read the hashes; a missing file yields no hashes
+    pub fn read(path: &Path) -> Result<Self, SyncError> {
+        match fs::read(path) {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SyncError> {
+        file_util::write_atomic(path, &serde_json::to_vec_pretty(self)?)?;
+
+        Ok(())
+    }
+}
+
+/// compute the SHA-256 hash of the manifest of a snapshot on the server
+pub fn manifest_sha256(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+) -> Result<String, SyncError> {
+    let path = manifest_path(snapshot_path);
+    let output = commands::server_exec(
+        exec,
+        server,
+        &[
+            "sha256sum",
+            path.to_str()
+                .ok_or_else(|| SyncError::PathConversionError("manifest".to_string()))?,
+        ],
+    )?;
+
+    match output.split_whitespace().next() {
+        Some(hash) => Ok(hash.to_string()),
+        None => Err(SyncError::UnexpectedOutput(output)),
+    }
+}
+
+pub fn read_manifest(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+) -> Result<Manifest, SyncError> {
+    let path = manifest_path(snapshot_path);
+    let content = commands::server_exec(
+        exec,
+        server,
+        &[
+            "cat",
+            path.to_str()
+                .ok_or_else(|| SyncError::PathConversionError("manifest".to_string()))?,
+        ],
+    )?;
+
+    Manifest::parse(&content)
+}
+
+/// create the manifest of a snapshot on the server; hashes are only computed for files not found unchanged in the manifest of the previous snapshot
+///
+/// Returns the manifest and the SHA-256 hash of the manifest file (see `ManifestHashes`).
+pub fn create_manifest(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+    previous: Option<&Path>,
+) -> Result<(Manifest, String), SyncError> {
+    let snapshot = snapshot_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?;
+    let files = parse_file_list(&commands::server_exec(
+        exec,
+        server,
+        &[
+            "find",
+            snapshot,
+            "-type",
+            "f",
+            "-printf",
+            "%P\\0%s\\0%T@\\0%m\\0",
+        ],
+    )?)?;
+    let previous = match previous {
+        Some(previous) => match read_manifest(exec, server, previous) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                log::warn!("could not read the previous manifest: {}", e);
+                Manifest::default()
+            }
+        },
+        None => Manifest::default(),
+    };
+    let mut manifest = Manifest::default();
+    let mut to_hash = Vec::new();
+
+    for file in files {
+        match previous.entries.get(&file.path) {
+            Some(entry) if entry.file == file => {
+                manifest.entries.insert(file.path.clone(), entry.clone());
+            }
+            _ => to_hash.push(file),
+        }
+    }
+
+    let metadata_dir = Path::new(snapshot).join(METADATA_DIR);
+    let metadata_dir = metadata_dir
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("metadata".to_string()))?;
+
+    commands::server_exec(exec, server, &["mkdir", "-p", metadata_dir])?;
+
+    if !to_hash.is_empty() {
+        let hashes = hash_files(
+            exec,
+            server,
+            snapshot_path,
+            &to_hash
+                .iter()
+                .map(|f| f.path.as_str())
+                .collect::<Vec<&str>>(),
+        )?;
+
+        for file in to_hash {
+            let hash = hashes
+                .get(&file.path)
+                .ok_or_else(|| SyncError::UnexpectedOutput(format!("no hash for {}", file.path)))?
+                .clone();
+
+            manifest
+                .entries
+                .insert(file.path.clone(), ManifestEntry { file, hash });
+        }
+    }
+
+    let manifest_file = TempFile::new("backup-rsync-manifest", manifest.serialize().as_bytes())?;
+    let manifest_sha256 = commands::file_sha256(exec, manifest_file.path())?;
+
+    commands::upload_file(
+        exec,
+        server,
+        manifest_file.path(),
+        &manifest_path(snapshot_path),
+    )?;
+
+    Ok((manifest, manifest_sha256))
+}
+
+/// compute the hashes of files of a snapshot on the server (paths relative to the snapshot); files that cannot be read are missing in the result
+fn hash_files(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+    paths: &[&str],
+) -> Result<BTreeMap<String, String>, SyncError> {
+    let snapshot = snapshot_path
+        .to_str()
+        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?;
+    // the list is passed on stdin, so that the snapshot is not modified (it may be read-only); NUL-separated, as paths may contain newlines
+    let list = paths.join("\0");
+    // xargs exits with 123 if sha256sum could not read some of the files; all other errors fail the command
+    let script = format!(
+        "cd {} || exit 1; xargs -r -0 sha256sum --; status=$?; [ $status -eq 0 ] || [ $status -eq 123 ]",
+        commands::shell_quote(snapshot)
+    );
+
+    parse_hashes(&commands::server_exec_with(
+        exec,
+        server,
+        &["sh", "-c", &script],
+        ExecOptions {
+            stdin: Some(list.as_bytes()),
+            ..Default::default()
+        },
+    )?)
+}
+
+/// check the files of a snapshot against the hashes in its manifest on the server; returns the paths of the files failing the check (including missing files)
+///
+/// A missing or empty manifest is an error, as is a manifest whose hash differs from the hash recorded when it was created (if given).
+pub fn check_manifest(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+    manifest_sha256: Option<&str>,
+) -> Result<Vec<String>, SyncError> {
+    if let Some(expected) = manifest_sha256 {
+        if self::manifest_sha256(exec, server, snapshot_path)? != expected {
+            return Err(SyncError::ManifestModified(
+                snapshot_path.to_string_lossy().to_string(),
+            ));
+        }
+    }
+
+    let manifest = read_manifest(exec, server, snapshot_path)?;
+
+    if manifest.entries.is_empty() {
+        return Err(SyncError::UnexpectedOutput(format!(
+            "the manifest of \"{}\" has no entries",
+            snapshot_path.to_string_lossy()
+        )));
+    }
+
+    let hashes = hash_files(
+        exec,
+        server,
+        snapshot_path,
+        &manifest
+            .entries
+            .keys()
+            .map(|p| p.as_str())
+            .collect::<Vec<&str>>(),
+    )?;
+
+    Ok(manifest
+        .entries
+        .values()
+        .filter(|entry| hashes.get(&entry.file.path) != Some(&entry.hash))
+        .map(|entry| entry.file.path.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+    use mockall::Sequence;
+
+    #[test]
+    fn parse_and_serialize() {
+        let content = "# backup-rsync manifest v2: path, size, mtime, mode, sha256
+a.txt\t3\t1667304000.0000000000\t644\tba7816bf
+dir/b.txt\t0\t1667304001.5000000000\t600\te3b0c442
+dir/tab\\tnew\\nline\\\\.txt\t0\t1667304001.5000000000\t600\te3b0c442
+";
+        let manifest = Manifest::parse(content).unwrap();
+
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(manifest.entries["dir/b.txt"].file.mode, "600");
+        assert!(manifest.entries.contains_key("dir/tab\tnew\nline\\.txt"));
+        assert_eq!(manifest.serialize(), content);
+
+        // paths in manifests of version 1 are not escaped
+        let manifest = Manifest::parse(
+            "# backup-rsync manifest v1: path, size, mtime, mode, sha256\ndir\\n.txt\t0\t1.0\t644\te3b0c442\n",
+        )
+        .unwrap();
+
+        assert!(manifest.entries.contains_key("dir\\n.txt"));
+    }
+
+    #[test]
+    fn diff() {
+        let old =
+            Manifest::parse("a\t1\t1.0\t644\tx\nb\t1\t1.0\t644\ty\nc\t1\t1.0\t644\tz\n").unwrap();
+        let new =
+            Manifest::parse("a\t1\t1.0\t644\tx\nb\t1\t2.0\t644\tw\nd\t1\t1.0\t644\tz\n").unwrap();
+
+        assert_eq!(
+            old.diff(&new),
+            SnapshotDiff {
+                added: vec!["d".to_string()],
+                removed: vec!["c".to_string()],
+                modified: vec!["b".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_hashes() {
+        let hashes = super::parse_hashes("ba7816bf  a.txt\n\\e3b0c442  dir/new\\nline\n").unwrap();
+
+        assert_eq!(hashes["a.txt"], "ba7816bf");
+        assert_eq!(hashes["dir/new\nline"], "e3b0c442");
+    }
+
+    #[test]
+    fn create_manifest() {
+        let mut seq = Sequence::new();
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(
+                    args[5..],
+                    [
+                        "find",
+                        "snapshot/new",
+                        "-type",
+                        "f",
+                        "-printf",
+                        "'%P\\0%s\\0%T@\\0%m\\0'"
+                    ]
+                );
+                Ok("unchanged.txt|3|1.0|644|changed.txt|4|2.0|644|new\tline\nname.txt|1|2.0|644|.backup-rsync/metadata.json|10|2.0|644|".replace('|', "\0"))
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(
+                    args[5..],
+                    ["cat", "snapshot/old/.backup-rsync/manifest.tsv"]
+                );
+                Ok("unchanged.txt\t3\t1.0\t644\tabc\nchanged.txt\t4\t1.0\t644\tdef\n".to_string())
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(args[5..], ["mkdir", "-p", "snapshot/new/.backup-rsync"]);
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(args[5..7], ["sh", "-c"]);
+                Ok("0123  changed.txt\n\\4567  new\tline\\nname.txt\n".to_string())
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, _| {
+                assert_eq!(command, "sha256sum");
+                Ok("89ab  manifest\n".to_string())
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "rsync");
+                assert_eq!(
+                    std::fs::read_to_string(args[2]).unwrap(),
+                    format!(
+                        "{}\nchanged.txt\t4\t2.0\t644\t0123\nnew\\tline\\nname.txt\t1\t2.0\t644\t4567\nunchanged.txt\t3\t1.0\t644\tabc\n",
+                        HEADER
+                    )
+                );
+                assert_eq!(args[3], "user@host:snapshot/new/.backup-rsync/manifest.tsv");
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        let (manifest, manifest_sha256) = super::create_manifest(
+            &mock,
+            Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+            Path::new("snapshot/new"),
+            Some(Path::new("snapshot/old")),
+        )
+        .unwrap();
+
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(manifest_sha256, "89ab");
+    }
+
+    #[test]
+    fn check_manifest() {
+        let mut seq = Sequence::new();
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(
+                    args[5..],
+                    ["sha256sum", "snapshot/new/.backup-rsync/manifest.tsv"]
+                );
+                Ok("89ab  snapshot/new/.backup-rsync/manifest.tsv\n".to_string())
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(
+                    args[5..],
+                    ["cat", "snapshot/new/.backup-rsync/manifest.tsv"]
+                );
+                Ok(format!(
+                    "{}\nok.txt\t3\t1.0\t644\tabc\ndir/rotten.bin\t4\t1.0\t644\tdef\ngone.txt\t1\t1.0\t644\t012\n",
+                    HEADER
+                ))
+            })
+            .in_sequence(&mut seq);
+        mock.expect_exec()
+            .times(1)
+            .returning(|_, args| {
+                assert_eq!(args[5..7], ["sh", "-c"]);
+                Ok("abc  ok.txt\n999  dir/rotten.bin\n".to_string())
+            })
+            .in_sequence(&mut seq);
+
+        assert_eq!(
+            super::check_manifest(
+                &mock,
+                Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+                Path::new("snapshot/new"),
+                Some("89ab")
+            )
+            .unwrap(),
+            vec!["dir/rotten.bin".to_string(), "gone.txt".to_string()]
+        );
+
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .returning(|_, _| Ok(format!("{}\n", HEADER)));
+
+        assert!(super::check_manifest(
+            &mock,
+            Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+            Path::new("snapshot/new"),
+            None
+        )
+        .is_err());
+
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .returning(|_, _| Ok("cdef  manifest.tsv\n".to_string()));
+
+        assert!(matches!(
+            super::check_manifest(
+                &mock,
+                Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+                Path::new("snapshot/new"),
+                Some("89ab")
+            ),
+            Err(SyncError::ManifestModified(_))
+        ));
+    }
+
+    /// executor recording the input of the command and returning the given output
+    struct Recording {
+        stdin: std::cell::RefCell<Vec<u8>>,
+        output: &'static str,
+    }
+
+    impl Executor for Recording {
+        fn exec_with(
+            &self,
+            command: &str,
+            args: &[&str],
+            options: ExecOptions,
+        ) -> Result<String, SyncError> {
+            assert_eq!(command, "sh");
+            assert_eq!(
+                args,
+                ["-c", "cd 'snapshot/a b' || exit 1; xargs -r -0 sha256sum --; status=$?; [ $status -eq 0 ] || [ $status -eq 123 ]"]
+            );
+            self.stdin
+                .borrow_mut()
+                .extend_from_slice(options.stdin.unwrap_or_default());
+            Ok(self.output.to_string())
+        }
+    }
+
+    #[test]
+    fn hash_files() {
+        let exec = Recording {
+            stdin: Default::default(),
+            output: "0123  a.txt\n\\4567  new\\nline.txt\n",
+        };
+
+        assert_eq!(
+            super::hash_files(
+                &exec,
+                Server::Local,
+                Path::new("snapshot/a b"),
+                &["a.txt", "new\nline.txt"]
+            )
+            .unwrap(),
+            BTreeMap::from([
+                ("a.txt".to_string(), "0123".to_string()),
+                ("new\nline.txt".to_string(), "4567".to_string())
+            ])
+        );
+        assert_eq!(exec.stdin.take(), b"a.txt\0new\nline.txt");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    commands::{self, Server},
+    executor::Executor,
+    manifest::METADATA_DIR,
+    rsync_stats::RsyncStats,
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+const METADATA_FILE: &str = "metadata.json";
+
+/// information about the run that created a snapshot; stored inside the snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotMetadata {
+    /// name of the snapshot when it was created
+    pub name: String,
+    pub job: String,
+    pub source_host: String,
+    pub tool_version: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// exit status of rsync (0 or 24 if files vanished during the transfer)
+    pub exit_status: i32,
+    pub stats: Option<RsyncStats>,
+    pub exclude_file_sha256: Option<String>,
+}
+
+/// get the path of the metadata file of a snapshot
+pub fn metadata_path(snapshot_path: &Path) -> PathBuf {
+    snapshot_path.join(METADATA_DIR).join(METADATA_FILE)
+}
+
+/// write the metadata into the snapshot on the server
+pub fn write_metadata(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_path: &Path,
+    metadata: &SnapshotMetadata,
+) -> Result<(), SyncError> {
+    commands::write_remote_file(
+        exec,
+        server,
+        &metadata_path(snapshot_path),
+        &serde_json::to_string(metadata)?,
+    )?;
+
+    Ok(())
+}
+
+/// read the metadata of the snapshots on the server; the result is in the same order as the paths and `None` for snapshots without (valid or readable) metadata
+pub fn read_metadata(
+    exec: &dyn Executor,
+    server: Server,
+    snapshot_paths: &[&Path],
+) -> Result<Vec<Option<SnapshotMetadata>>, SyncError> {
+    if snapshot_paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let paths = snapshot_paths
+        .iter()
+        .map(|p| {
+            p.to_str()
+                .map(|p| commands::shell_quote(p).to_string())
+                .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))
+        })
+        .collect::<Result<Vec<String>, SyncError>>()?;
+    // every snapshot is introduced by its path enclosed in NUL characters, so the content of a metadata file cannot shift the entries of the other snapshots
+    let script = format!(
+        "for d in {}; do printf '\\0%s\\0' \"$d\"; cat \"$d/{}/{}\" 2>/dev/null || true; done",
+        paths.join(" "),
+        METADATA_DIR,
+        METADATA_FILE
+    );
+    let output = commands::server_exec(exec, server, &["sh", "-c", &script])?;
+    let mut entries = HashMap::new();
+    let mut fields = output.split('\0').skip(1);
+
+    while let (Some(path), Some(content)) = (fields.next(), fields.next()) {
+        entries.insert(path, content);
+    }
+
+    Ok(snapshot_paths
+        .iter()
+        .map(|path| {
+            match path
+                .to_str()
+                .and_then(|p| entries.get(p))
+                .map(|content| content.trim())
+            {
+                None | Some("") => None,
+                Some(content) => serde_json::from_str(content)
+                    .map_err(|e| {
+                        log::warn!(
+                            "invalid metadata in snapshot \"{}\": {}",
+                            path.to_string_lossy(),
+                            e
+                        )
+                    })
+                    .ok(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+    use chrono::TimeZone;
+
+    fn metadata(name: &str) -> SnapshotMetadata {
+        SnapshotMetadata {
+            name: name.to_string(),
+            job: "job".to_string(),
+            source_host: "laptop".to_string(),
+            tool_version: "0.1.0".to_string(),
+            start: Utc.ymd(2022, 11, 1).and_hms(12, 0, 0),
+            end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+            exit_status: 0,
+            stats: None,
+            exclude_file_sha256: Some("e3b0c442".to_string()),
+        }
+    }
+
+    #[test]
+    fn write_metadata() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "ssh");
+            assert_eq!(args[5..7], ["sh", "-c"]);
+            assert!(args[7].contains("snapshot/a/.backup-rsync/metadata.json"));
+            assert!(args[7].contains(r#""source_host":"laptop""#));
+            Ok(String::new())
+        });
+
+        super::write_metadata(
+            &mock,
+            Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+            Path::new("snapshot/a"),
+            &metadata("a"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn read_metadata() {
+        let mut mock = exec_rs::MockExec::new();
+        // b has no metadata, d a multi-line metadata file and e an invalid one; f is missing in the output
+        let output = format!(
+            "|snapshot/a|{}\n|snapshot/b||snapshot/c|{}\n|snapshot/d|{{\n}}\nextra\n|snapshot/e|{{\"name\":",
+            serde_json::to_string(&metadata("a")).unwrap(),
+            serde_json::to_string(&metadata("c")).unwrap()
+        )
+        .replace('|', "\0");
+
+        mock.expect_exec().once().returning(move |_, args| {
+            assert_eq!(args[5..7], ["sh", "-c"]);
+            assert!(args[7].contains(
+                "for d in snapshot/a snapshot/b snapshot/c snapshot/d snapshot/e snapshot/f; do"
+            ));
+            Ok(output.clone())
+        });
+
+        let res = super::read_metadata(
+            &mock,
+            Server::Remote(&SshCredentials::test("user", "id_file", "host")),
+            &[
+                Path::new("snapshot/a"),
+                Path::new("snapshot/b"),
+                Path::new("snapshot/c"),
+                Path::new("snapshot/d"),
+                Path::new("snapshot/e"),
+                Path::new("snapshot/f"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            res,
+            vec![
+                Some(metadata("a")),
+                None,
+                Some(metadata("c")),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+}
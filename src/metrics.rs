@@ -0,0 +1,178 @@
+use crate::{
+    file_util,
+    outcome::{RunOutcome, RunStatus},
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Utc};
+use std::{fmt::Write, path::Path};
+
+/// render the metrics of a run in the Prometheus text format
+pub fn render(outcome: &RunOutcome, suffix: &str, last_success: Option<DateTime<Utc>>) -> String {
+    let labels = format!(
+        "job=\"{}\",suffix=\"{}\"",
+        escape_label(&outcome.job),
+        escape_label(suffix)
+    );
+    let stats = outcome.stats.clone().unwrap_or_default();
+    let metrics = [
+        (
+            "backup_rsync_last_run_timestamp_seconds",
+            "Start time of the last run.",
+            Some(outcome.start.timestamp() as f64),
+        ),
+        (
+            "backup_rsync_last_run_success",
+            "Whether the last run succeeded.",
+            Some(match outcome.status {
+                RunStatus::Success => 1.0,
+                RunStatus::Failure | RunStatus::Partial | RunStatus::Interrupted => 0.0,
+            }),
+        ),
+        (
+            "backup_rsync_last_success_timestamp_seconds",
+            "End time of the last successful run.",
+            last_success.map(|t| t.timestamp() as f64),
+        ),
+        (
+            "backup_rsync_last_run_duration_seconds",
+            "Duration of the last run.",
+            Some((outcome.end - outcome.start).num_milliseconds() as f64 / 1000.0),
+        ),
+        (
+            "backup_rsync_last_run_sent_bytes",
+            "Bytes sent by rsync during the last run.",
+            stats.bytes_sent.map(|b| b as f64),
+        ),
+        (
+            "backup_rsync_last_run_received_bytes",
+            "Bytes received by rsync during the last run.",
+            stats.bytes_received.map(|b| b as f64),
+        ),
+        (
+            "backup_rsync_last_run_transferred_files",
+            "Files transferred by rsync during the last run.",
+            stats.files_transferred.map(|f| f as f64),
+        ),
+        (
+            "backup_rsync_snapshots",
+            "Number of snapshots after pruning.",
+            outcome.snapshot_count.map(|c| c as f64),
+        ),
+        (
+            "backup_rsync_oldest_snapshot_age_seconds",
+            "Age of the oldest snapshot.",
+            outcome
+                .oldest_snapshot
+                .map(|t| (outcome.end - t).num_seconds() as f64),
+        ),
+        (
+            "backup_rsync_newest_snapshot_age_seconds",
+            "Age of the newest snapshot.",
+            outcome
+                .newest_snapshot
+                .map(|t| (outcome.end - t).num_seconds() as f64),
+        ),
+        (
+            "backup_rsync_last_run_pruned_snapshots",
+            "Number of snapshots removed during the last run.",
+            Some(outcome.pruned as f64),
+        ),
+    ];
+
+    let mut res = String::new();
+
+    for (name, help, value) in metrics {
+        if let Some(value) = value {
+            // writing to a string cannot fail
+            let _ = write!(
+                res,
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name}{{{labels}}} {value}\n",
+            );
+        }
+    }
+
+    res
+}
+
+/// write the metrics atomically so that the textfile collector never reads a partial file
+pub fn write(
+    path: &Path,
+    outcome: &RunOutcome,
+    suffix: &str,
+    last_success: Option<DateTime<Utc>>,
+) -> Result<(), SyncError> {
+    file_util::write_atomic(path, render(outcome, suffix, last_success).as_bytes())?;
+
+    Ok(())
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsync_stats::RsyncStats;
+    use chrono::TimeZone;
+
+    #[test]
+    fn render() {
+        let outcome = RunOutcome {
+            status: RunStatus::Success,
+            end: Utc.ymd(2022, 11, 2).and_hms(12, 1, 30),
+            stats: Some(RsyncStats {
+                bytes_sent: Some(4321),
+                bytes_received: Some(35),
+                files_transferred: Some(1),
+                ..Default::default()
+            }),
+            snapshot_count: Some(2),
+            oldest_snapshot: Some(Utc.ymd(2022, 11, 1).and_hms(12, 0, 0)),
+            newest_snapshot: Some(Utc.ymd(2022, 11, 2).and_hms(12, 0, 0)),
+            pruned: 1,
+            ..RunOutcome::new("job \"a\"", Utc.ymd(2022, 11, 2).and_hms(12, 0, 0))
+        };
+
+        assert_eq!(
+            super::render(&outcome, "test", Some(outcome.end)),
+            r#"# HELP backup_rsync_last_run_timestamp_seconds Start time of the last run.
+# TYPE backup_rsync_last_run_timestamp_seconds gauge
+backup_rsync_last_run_timestamp_seconds{job="job \"a\"",suffix="test"} 1667390400
+# HELP backup_rsync_last_run_success Whether the last run succeeded.
+# TYPE backup_rsync_last_run_success gauge
+backup_rsync_last_run_success{job="job \"a\"",suffix="test"} 1
+# HELP backup_rsync_last_success_timestamp_seconds End time of the last successful run.
+# TYPE backup_rsync_last_success_timestamp_seconds gauge
+backup_rsync_last_success_timestamp_seconds{job="job \"a\"",suffix="test"} 1667390490
+# HELP backup_rsync_last_run_duration_seconds Duration of the last run.
+# TYPE backup_rsync_last_run_duration_seconds gauge
+backup_rsync_last_run_duration_seconds{job="job \"a\"",suffix="test"} 90
+# HELP backup_rsync_last_run_sent_bytes Bytes sent by rsync during the last run.
+# TYPE backup_rsync_last_run_sent_bytes gauge
+backup_rsync_last_run_sent_bytes{job="job \"a\"",suffix="test"} 4321
+# HELP backup_rsync_last_run_received_bytes Bytes received by rsync during the last run.
+# TYPE backup_rsync_last_run_received_bytes gauge
+backup_rsync_last_run_received_bytes{job="job \"a\"",suffix="test"} 35
+# HELP backup_rsync_last_run_transferred_files Files transferred by rsync during the last run.
+# TYPE backup_rsync_last_run_transferred_files gauge
+backup_rsync_last_run_transferred_files{job="job \"a\"",suffix="test"} 1
+# HELP backup_rsync_snapshots Number of snapshots after pruning.
+# TYPE backup_rsync_snapshots gauge
+backup_rsync_snapshots{job="job \"a\"",suffix="test"} 2
+# HELP backup_rsync_oldest_snapshot_age_seconds Age of the oldest snapshot.
+# TYPE backup_rsync_oldest_snapshot_age_seconds gauge
+backup_rsync_oldest_snapshot_age_seconds{job="job \"a\"",suffix="test"} 86490
+# HELP backup_rsync_newest_snapshot_age_seconds Age of the newest snapshot.
+# TYPE backup_rsync_newest_snapshot_age_seconds gauge
+backup_rsync_newest_snapshot_age_seconds{job="job \"a\"",suffix="test"} 90
+# HELP backup_rsync_last_run_pruned_snapshots Number of snapshots removed during the last run.
+# TYPE backup_rsync_last_run_pruned_snapshots gauge
+backup_rsync_last_run_pruned_snapshots{job="job \"a\"",suffix="test"} 1
+"#
+        );
+    }
+}
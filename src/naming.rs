@@ -0,0 +1,425 @@
+use crate::{
+    clock::SnapshotTimeZone,
+    commands::{self, Server},
+    config::Config,
+    executor::Executor,
+    sync_error::SyncError,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc,
+};
+use std::path::Path;
+
+/// template of the snapshot names used before the naming scheme became configurable
+pub const DEFAULT_TEMPLATE: &str = "{date}_{suffix}";
+
+/// names of the snapshots built from a template
+///
+/// The template contains exactly one `{date}` (RFC 3339) or `{date:<strftime format>}` placeholder and optionally `{host}`, `{job}` and `{suffix}`.
+/// A `/` in the template creates nested directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingScheme {
+    prefix: String,
+    date_format: Option<String>,
+    postfix: String,
+    time_zone: SnapshotTimeZone,
+}
+
+impl NamingScheme {
+    /// create the naming scheme from a template and the values of the placeholders
+    pub fn new(template: &str, host: &str, job: &str, suffix: &str) -> Result<Self, SyncError> {
+        let invalid =
+            |reason: &str| SyncError::InvalidTemplate(template.to_string(), reason.to_string());
+        let mut prefix = String::new();
+        let mut date_format = None;
+        let mut postfix = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find(['{', '}']) {
+            let literal = &rest[..start];
+
+            match date_format {
+                None => prefix.push_str(literal),
+                Some(_) => postfix.push_str(literal),
+            }
+
+            if rest[start..].starts_with('}') {
+                return Err(invalid("unmatched \"}\""));
+            }
+
+            let end = start
+                + rest[start..]
+                    .find('}')
+                    .ok_or_else(|| invalid("unmatched \"{\""))?;
+            let (name, format) = match rest[start + 1..end].split_once(':') {
+                Some((name, format)) => (name, Some(format)),
+                None => (&rest[start + 1..end], None),
+            };
+            let value = match (name, format) {
+                ("date", format) => {
+                    if date_format.is_some() {
+                        return Err(invalid("\"{date}\" must occur exactly once"));
+                    }
+                    if let Some(format) = format {
+                        if format.is_empty() || StrftimeItems::new(format).any(|i| i == Item::Error)
+                        {
+                            return Err(invalid("invalid date format"));
+                        }
+                    }
+
+                    date_format = Some(format.map(|f| f.to_string()));
+                    None
+                }
+                ("host", None) => Some(host),
+                ("job", None) => Some(job),
+                ("suffix", None) => Some(suffix),
+                _ => {
+                    return Err(invalid(&format!(
+                        "unknown placeholder \"{}\"",
+                        &rest[start..=end]
+                    )))
+                }
+            };
+
+            if let Some(value) = value {
+                match date_format {
+                    None => prefix.push_str(value),
+                    Some(_) => postfix.push_str(value),
+                }
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        match date_format {
+            None => Err(invalid("\"{date}\" must occur exactly once")),
+            Some(date_format) => {
+                postfix.push_str(rest);
+
+                if prefix.starts_with('/')
+                    || postfix.ends_with('/')
+                    || format!("{}x{}", prefix, postfix)
+                        .split('/')
+                        .any(|c| c.is_empty() || c == "." || c == "..")
+                {
+                    return Err(invalid("invalid path"));
+                }
+
+                Ok(Self {
+                    prefix,
+                    date_format,
+                    postfix,
+                    time_zone: SnapshotTimeZone::Utc,
+                })
+            }
+        }
+    }
+
+    /// use the time zone for the dates in the names (instead of UTC)
+    pub fn with_time_zone(mut self, time_zone: SnapshotTimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// create the naming scheme configured for the job
+    pub fn from_config(config: &Config) -> Result<Self, SyncError> {
+        Ok(Self::new(
+            config.snapshot_name.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+            &config.source_host(),
+            config.job_name(),
+            &config.snapshot_suffix,
+        )?
+        .with_time_zone(config.time_zone))
+    }
+
+    /// name of the snapshot created at the given time
+    pub fn format(&self, date_time: &DateTime<Utc>) -> String {
+        let date_time = self.time_zone.convert(date_time);
+        let date = match &self.date_format {
+            None => date_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            Some(format) => date_time.format(format).to_string(),
+        };
+
+        format!("{}{}{}", self.prefix, date, self.postfix)
+    }
+
+    /// get the time of a snapshot from its name; `None` if the name does not match the scheme
+    pub fn parse(&self, name: &str) -> Option<DateTime<Utc>> {
+        let date = name
+            .strip_prefix(&self.prefix)?
+            .strip_suffix(&self.postfix)?;
+
+        if date.is_empty() || date.split('/').count() != self.date_depth() {
+            return None;
+        }
+
+        match &self.date_format {
+            None => DateTime::parse_from_rfc3339(date).ok().map(|d| d.into()),
+            // dates without offset are in the configured time zone
+            Some(format) => match DateTime::parse_from_str(date, format) {
+                Ok(date) => Some(date.into()),
+                Err(_) => NaiveDateTime::parse_from_str(date, format)
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(date, format).map(|d| d.and_hms(0, 0, 0))
+                    })
+                    .ok()
+                    .and_then(|date| self.time_zone.from_local(&date)),
+            },
+        }
+    }
+
+    /// number of directory levels below the snapshot directory used by the names
+    pub fn depth(&self) -> usize {
+        self.prefix.matches('/').count() + self.postfix.matches('/').count() + self.date_depth()
+    }
+
+    fn date_depth(&self) -> usize {
+        self.date_format
+            .as_deref()
+            .map(|f| f.matches('/').count())
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// split entries of the snapshot directory into snapshots (with their time) and unrecognized entries
+    pub fn split(&self, entries: Vec<String>) -> (Vec<(DateTime<Utc>, String)>, Vec<String>) {
+        let mut snapshots = Vec::new();
+        let mut unrecognized = Vec::new();
+
+        for entry in entries {
+            match self.parse(&entry) {
+                Some(date) => snapshots.push((date, entry)),
+                None => unrecognized.push(entry),
+            }
+        }
+
+        (snapshots, unrecognized)
+    }
+}
+
+/// get the entries of the snapshot directory that do not match the naming scheme
+pub fn unrecognized_entries(
+    exec: &dyn Executor,
+    config: &Config,
+) -> Result<Vec<String>, SyncError> {
+    let scheme = NamingScheme::from_config(config)?;
+    let entries = commands::get_snapshot_entries(
+        exec,
+        config.server(),
+        Path::new(&config.snapshot),
+        scheme.depth(),
+    )?;
+
+    Ok(scheme.split(entries).1)
+}
+
+/// rename the snapshots named according to an old template to the configured naming scheme; returns the renamed snapshots
+pub fn migrate_names(
+    exec: &dyn Executor,
+    config: &Config,
+    from: &str,
+    dry_run: bool,
+) -> Result<Vec<(String, String)>, SyncError> {
+    let from = NamingScheme::new(
+        from,
+        &config.source_host(),
+        config.job_name(),
+        &config.snapshot_suffix,
+    )?;
+    let to = NamingScheme::from_config(config)?;
+    let snapshot_dir = Path::new(&config.snapshot);
+    let (snapshots, _) = from.split(commands::get_snapshot_entries(
+        exec,
+        config.server(),
+        snapshot_dir,
+        from.depth(),
+    )?);
+    let renames = snapshots
+        .into_iter()
+        .map(|(date, name)| (name, to.format(&date)))
+        .filter(|(old, new)| old != new)
+        .collect::<Vec<(String, String)>>();
+
+    if !dry_run {
+        for (old, new) in &renames {
+            log::info!("renaming snapshot \"{}\" to \"{}\"", old, new);
+            rename_snapshot(
+                exec,
+                config.server(),
+                &snapshot_dir.join(old),
+                &snapshot_dir.join(new),
+            )?;
+        }
+    }
+
+    Ok(renames)
+}
+
+/// move a snapshot on the server, creating the parent directory if necessary; fails if the target exists
+fn rename_snapshot(
+    exec: &dyn Executor,
+    server: Server,
+    old: &Path,
+    new: &Path,
+) -> Result<String, SyncError> {
+    commands::server_exec(
+        exec,
+        server,
+        &[
+            "sh",
+            "-c",
+            "test ! -e \"$2\" && mkdir -p \"$(dirname \"$2\")\" && mv -- \"$1\" \"$2\"",
+            "sh",
+            old.to_str()
+                .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?,
+            new.to_str()
+                .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?,
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+    use chrono::TimeZone;
+
+    #[test]
+    fn default_template() {
+        let scheme = NamingScheme::new(DEFAULT_TEMPLATE, "laptop", "job", "test_user").unwrap();
+        let date = Utc.ymd(2022, 11, 1).and_hms(12, 0, 0);
+
+        assert_eq!(scheme.format(&date), "2022-11-01T12:00:00Z_test_user");
+        assert_eq!(scheme.parse("2022-11-01T12:00:00Z_test_user"), Some(date));
+        assert_eq!(scheme.parse("2022-11-01T12:00:00Z_other"), None);
+        assert_eq!(scheme.parse("lost+found"), None);
+        assert_eq!(scheme.depth(), 1);
+    }
+
+    #[test]
+    fn nested_template() {
+        let scheme = NamingScheme::new(
+            "{host}/{date:%Y-%m-%dT%H%M}-{suffix}",
+            "laptop",
+            "job",
+            "home",
+        )
+        .unwrap();
+        let date = Utc.ymd(2022, 11, 1).and_hms(12, 30, 0);
+
+        assert_eq!(scheme.format(&date), "laptop/2022-11-01T1230-home");
+        assert_eq!(scheme.parse("laptop/2022-11-01T1230-home"), Some(date));
+        assert_eq!(scheme.parse("desktop/2022-11-01T1230-home"), None);
+        assert_eq!(scheme.parse("laptop/x/2022-11-01T1230-home"), None);
+        assert_eq!(scheme.depth(), 2);
+
+        let scheme = NamingScheme::new("{date:%Y/%m-%d}", "laptop", "job", "home").unwrap();
+
+        assert_eq!(
+            scheme.parse("2022/11-01"),
+            Some(Utc.ymd(2022, 11, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(scheme.depth(), 2);
+    }
+
+    #[test]
+    fn from_config_host() {
+        let config = Config {
+            ssh_credentials: SshCredentials {
+                host: "client".to_string(),
+                ..Default::default()
+            },
+            mode: crate::config::SyncMode::Pull,
+            snapshot_suffix: "home".to_string(),
+            snapshot_name: Some("{host}/{date}".to_string()),
+            ..Default::default()
+        };
+        let date = Utc.ymd(2022, 11, 1).and_hms(12, 0, 0);
+
+        assert_eq!(
+            NamingScheme::from_config(&config).unwrap().format(&date),
+            "client/2022-11-01T12:00:00Z"
+        );
+    }
+
+    #[test]
+    fn time_zone() {
+        let date = Utc.ymd(2022, 11, 1).and_hms(23, 30, 0);
+        let offset = SnapshotTimeZone::Fixed(chrono::FixedOffset::east(3600));
+        let scheme = NamingScheme::new(DEFAULT_TEMPLATE, "laptop", "job", "home")
+            .unwrap()
+            .with_time_zone(offset);
+
+        assert_eq!(scheme.format(&date), "2022-11-02T00:30:00+01:00_home");
+        assert_eq!(scheme.parse("2022-11-02T00:30:00+01:00_home"), Some(date));
+        assert_eq!(scheme.parse("2022-11-01T23:30:00Z_home"), Some(date));
+
+        let scheme = NamingScheme::new("{date:%Y-%m-%d_%H%M}-{suffix}", "laptop", "job", "home")
+            .unwrap()
+            .with_time_zone(offset);
+
+        assert_eq!(scheme.format(&date), "2022-11-02_0030-home");
+        assert_eq!(scheme.parse("2022-11-02_0030-home"), Some(date));
+    }
+
+    #[test]
+    fn invalid_template() {
+        for template in [
+            "{suffix}",
+            "{date}{date}",
+            "{date}_{user}",
+            "{date:%Q}",
+            "{date",
+            "date}",
+            "/{date}",
+            "{date}/",
+            "a//{date}",
+            "../{date}",
+        ] {
+            assert!(
+                NamingScheme::new(template, "laptop", "job", "home").is_err(),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn migrate_names() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..], ["ls", "-A1", "snapshot"]);
+            Ok("2022-11-01T12:00:00Z_home\nlaptop\n".to_string())
+        });
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..7], ["sh", "-c"]);
+            assert_eq!(
+                args[8..],
+                [
+                    "sh",
+                    "snapshot/2022-11-01T12:00:00Z_home",
+                    "snapshot/home/2022-11-01T1200"
+                ]
+            );
+            Ok(String::new())
+        });
+
+        let config = Config {
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "home".to_string(),
+            snapshot_name: Some("{suffix}/{date:%Y-%m-%dT%H%M}".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::migrate_names(&mock, &config, DEFAULT_TEMPLATE, false).unwrap(),
+            vec![(
+                "2022-11-01T12:00:00Z_home".to_string(),
+                "home/2022-11-01T1200".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::{
+    executor::Executor,
+    file_util::TempFile,
+    outcome::{RunOutcome, RunStatus},
+    sync_error::SyncError,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Notification {
+    #[serde(default)]
+    pub when: NotifyWhen,
+    pub sink: NotificationSink,
+}
+
+/// when a notification is sent
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyWhen {
+    Always,
+    #[default]
+    OnFailure,
+    OnStateChange,
+}
+
+impl NotifyWhen {
+    /// decide whether to notify given the current and the previous status
+    pub fn applies(&self, status: RunStatus, previous: Option<RunStatus>) -> bool {
+        match self {
+            NotifyWhen::Always => true,
+            NotifyWhen::OnFailure => status != RunStatus::Success,
+            NotifyWhen::OnStateChange => previous != Some(status),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationSink {
+    /// send an email using curl (e.g. url "smtps://mail.example.com:465")
+    Smtp {
+        url: String,
+        from: String,
+        to: Vec<String>,
+        user: Option<String>,
+        password: Option<String>,
+    },
+    /// post the outcome as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: Vec<String>,
+    },
+    /// run a local command with the outcome in its environment
+    Command { command: String },
+}
+
+/// send the outcome of a run to a notification sink
+pub fn send(
+    exec: &dyn Executor,
+    sink: &NotificationSink,
+    outcome: &RunOutcome,
+) -> Result<(), SyncError> {
+    match sink {
+        NotificationSink::Smtp {
+            url,
+            from,
+            to,
+            user,
+            password,
+        } => {
+            // curl --url ${url} --mail-from ${from} --mail-rcpt ${to} --upload-file ${message}
+            let message = TempFile::new(
+                "backup-rsync-mail",
+                mail_message(from, to, outcome).as_bytes(),
+            )?;
+            let message_path = message.path().to_string_lossy();
+            let credentials = match (user, password) {
+                (Some(user), Some(password)) => Some(format!("{}:{}", user, password)),
+                (Some(user), None) => Some(user.clone()),
+                _ => None,
+            };
+            let config = curl_config(
+                &credentials
+                    .iter()
+                    .map(|c| ("user", c.as_str()))
+                    .collect::<Vec<(&str, &str)>>(),
+            )?;
+            let config_path = config.as_ref().map(|c| c.path().to_string_lossy());
+            let mut args = vec![
+                "--silent",
+                "--show-error",
+                "--url",
+                url,
+                "--mail-from",
+                from,
+            ];
+
+            for recipient in to {
+                args.push("--mail-rcpt");
+                args.push(recipient);
+            }
+
+            if let Some(config_path) = &config_path {
+                args.push("--config");
+                args.push(config_path);
+            }
+
+            args.push("--upload-file");
+            args.push(&message_path);
+            exec.exec("curl", &args[..])?;
+        }
+        NotificationSink::Webhook { url, headers } => {
+            // curl -X POST -H "Content-Type: application/json" --data-binary ${outcome} ${url}
+            let body = serde_json::to_string(outcome)?;
+            // the headers may contain credentials (e.g. "Authorization: Bearer ...")
+            let config = curl_config(
+                &headers
+                    .iter()
+                    .map(|h| ("header", h.as_str()))
+                    .collect::<Vec<(&str, &str)>>(),
+            )?;
+            let config_path = config.as_ref().map(|c| c.path().to_string_lossy());
+            let mut args = vec![
+                "--silent",
+                "--show-error",
+                "--fail",
+                "-X",
+                "POST",
+                "-H",
+                "Content-Type: application/json",
+            ];
+
+            if let Some(config_path) = &config_path {
+                args.push("--config");
+                args.push(config_path);
+            }
+
+            args.push("--data-binary");
+            args.push(&body);
+            args.push(url);
+            exec.exec("curl", &args[..])?;
+        }
+        NotificationSink::Command { command } => {
+            let env = [
+                format!("BACKUP_RSYNC_JOB={}", outcome.job),
+                format!("BACKUP_RSYNC_STATUS={}", outcome.status.name()),
+                format!(
+                    "BACKUP_RSYNC_SNAPSHOT={}",
+                    outcome.snapshot.as_deref().unwrap_or_default()
+                ),
+                format!(
+                    "BACKUP_RSYNC_ERROR={}",
+                    outcome.error.as_deref().unwrap_or_default()
+                ),
+                format!("BACKUP_RSYNC_OUTCOME={}", serde_json::to_string(outcome)?),
+            ];
+            let mut args: Vec<&str> = env.iter().map(|e| e.as_str()).collect();
+
+            args.push("sh");
+            args.push("-c");
+            args.push(command);
+            exec.exec("env", &args[..])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// write options to a curl config file readable only by the owner, so that credentials are not on the command line visible to other users; `None` without options
+fn curl_config(options: &[(&str, &str)]) -> Result<Option<TempFile>, SyncError> {
+    if options.is_empty() {
+        return Ok(None);
+    }
+
+    let config = options
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+                .replace('\t', "\\t");
+
+            format!("{} = \"{}\"\n", name, value)
+        })
+        .collect::<String>();
+
+    Ok(Some(TempFile::new("backup-rsync-curl", config.as_bytes())?))
+}
+
+fn mail_message(from: &str, to: &[String], outcome: &RunOutcome) -> String {
+    let mut body = format!(
+        "job: {}\r\nstatus: {}\r\nstart: {}\r\nend: {}\r\n",
+        outcome.job,
+        outcome.status.name(),
+        outcome.start.to_rfc3339(),
+        outcome.end.to_rfc3339()
+    );
+
+    if let Some(snapshot) = &outcome.snapshot {
+        body.push_str(&format!("snapshot: {}\r\n", snapshot));
+    }
+
+    if let Some(error) = &outcome.error {
+        body.push_str(&format!("error: {}\r\n", error));
+    }
+
+    format!(
+        "From: {}\r\nTo: {}\r\nDate: {}\r\nSubject: backup \"{}\": {}\r\n\r\n{}",
+        from,
+        to.join(", "),
+        outcome.end.to_rfc2822(),
+        outcome.job,
+        outcome.status.name(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    fn outcome() -> RunOutcome {
+        RunOutcome {
+            end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+            error: Some("rsync failed".to_string()),
+            ..RunOutcome::new("job", Utc.ymd(2022, 11, 1).and_hms(12, 0, 0))
+        }
+    }
+
+    #[test]
+    fn notify_when() {
+        assert!(NotifyWhen::Always.applies(RunStatus::Success, Some(RunStatus::Success)));
+        assert!(NotifyWhen::OnFailure.applies(RunStatus::Failure, Some(RunStatus::Failure)));
+        assert!(!NotifyWhen::OnFailure.applies(RunStatus::Success, Some(RunStatus::Failure)));
+        assert!(NotifyWhen::OnFailure.applies(RunStatus::Partial, Some(RunStatus::Success)));
+        assert!(NotifyWhen::OnStateChange.applies(RunStatus::Success, Some(RunStatus::Failure)));
+        assert!(NotifyWhen::OnStateChange.applies(RunStatus::Success, None));
+        assert!(!NotifyWhen::OnStateChange.applies(RunStatus::Failure, Some(RunStatus::Failure)));
+    }
+
+    #[test]
+    fn send_webhook() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "curl");
+            assert_eq!(
+                args,
+                vec![
+                    "--silent",
+                    "--show-error",
+                    "--fail",
+                    "-X",
+                    "POST",
+                    "-H",
+                    "Content-Type: application/json",
+                    "--config",
+                    args[8],
+                    "--data-binary",
+                    r#"{"job":"job","status":"failure","start":"2022-11-01T12:00:00Z","end":"2022-11-01T12:05:00Z","snapshot":null,"error":"rsync failed","stats":null,"snapshot_count":null,"oldest_snapshot":null,"newest_snapshot":null,"pruned":0}"#,
+                    "http://localhost:8080/hook",
+                ]
+            );
+            assert_eq!(
+                std::fs::read_to_string(args[8]).unwrap(),
+                "header = \"Authorization: Bearer token\"\n"
+            );
+            Ok("".to_string())
+        });
+
+        send(
+            &mock,
+            &NotificationSink::Webhook {
+                url: "http://localhost:8080/hook".to_string(),
+                headers: vec!["Authorization: Bearer token".to_string()],
+            },
+            &outcome(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn send_smtp() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "curl");
+            assert_eq!(
+                args[..12],
+                [
+                    "--silent",
+                    "--show-error",
+                    "--url",
+                    "smtp://localhost:2525",
+                    "--mail-from",
+                    "backup@example.com",
+                    "--mail-rcpt",
+                    "admin@example.com",
+                    "--config",
+                    args[9],
+                    "--upload-file",
+                    args[11],
+                ]
+            );
+            assert_eq!(
+                std::fs::read_to_string(args[9]).unwrap(),
+                "user = \"backup:se\\\"cret\"\n"
+            );
+
+            let message = std::fs::read_to_string(args[11]).unwrap();
+
+            assert!(message.contains("Subject: backup \"job\": failure\r\n"));
+            assert!(message.contains("error: rsync failed\r\n"));
+            Ok("".to_string())
+        });
+
+        send(
+            &mock,
+            &NotificationSink::Smtp {
+                url: "smtp://localhost:2525".to_string(),
+                from: "backup@example.com".to_string(),
+                to: vec!["admin@example.com".to_string()],
+                user: Some("backup".to_string()),
+                password: Some("se\"cret".to_string()),
+            },
+            &outcome(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn send_command() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "env");
+            assert_eq!(
+                args[..4],
+                [
+                    "BACKUP_RSYNC_JOB=job",
+                    "BACKUP_RSYNC_STATUS=failure",
+                    "BACKUP_RSYNC_SNAPSHOT=",
+                    "BACKUP_RSYNC_ERROR=rsync failed",
+                ]
+            );
+            assert_eq!(args[5..], ["sh", "-c", "notify-send backup"]);
+            Ok("".to_string())
+        });
+
+        send(
+            &mock,
+            &NotificationSink::Command {
+                command: "notify-send backup".to_string(),
+            },
+            &outcome(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn send_webhook_to_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+
+            loop {
+                let mut line = String::new();
+
+                reader.read_line(&mut line).unwrap();
+
+                if line == "\r\n" {
+                    break;
+                }
+
+                if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+
+            let mut body = vec![0; content_length];
+
+            reader.read_exact(&mut body).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+
+        send(
+            &crate::executor::ProcessExecutor {},
+            &NotificationSink::Webhook {
+                url,
+                headers: vec![],
+            },
+            &outcome(),
+        )
+        .unwrap();
+
+        let received: RunOutcome = serde_json::from_str(&server.join().unwrap()).unwrap();
+
+        assert_eq!(received, outcome());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::rsync_stats::RsyncStats;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// result of a single backup run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunOutcome {
+    pub job: String,
+    pub status: RunStatus,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub snapshot: Option<String>,
+    pub error: Option<String>,
+    pub stats: Option<RsyncStats>,
+    /// number of snapshots remaining after pruning
+    pub snapshot_count: Option<usize>,
+    pub oldest_snapshot: Option<DateTime<Utc>>,
+    pub newest_snapshot: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pruned: usize,
+}
+
+impl RunOutcome {
+    /// outcome of a run that has just started
+    pub fn new(job: &str, start: DateTime<Utc>) -> Self {
+        Self {
+            job: job.to_string(),
+            status: RunStatus::Failure,
+            start,
+            end: start,
+            snapshot: None,
+            error: None,
+            stats: None,
+            snapshot_count: None,
+            oldest_snapshot: None,
+            newest_snapshot: None,
+            pruned: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    Failure,
+    /// the transfer was stopped at the deadline; no snapshot was created
+    Partial,
+    /// the run was stopped by SIGINT or SIGTERM; if no snapshot was created, the next run resumes it
+    Interrupted,
+}
+
+impl RunStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunStatus::Success => "success",
+            RunStatus::Failure => "failure",
+            RunStatus::Partial => "partial",
+            RunStatus::Interrupted => "interrupted",
+        }
+    }
+}
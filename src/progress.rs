@@ -0,0 +1,225 @@
+use crate::list;
+use std::{
+    io::{IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// interval of the log lines reporting the progress if stdout is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// phase of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Sync,
+    Snapshot,
+    List,
+    /// hashing the files of the new snapshot (if `manifest` is set)
+    Manifest,
+    Prune,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Sync => "sync",
+            Phase::Snapshot => "snapshot",
+            Phase::List => "list",
+            Phase::Manifest => "manifest",
+            Phase::Prune => "prune",
+        }
+    }
+}
+
+/// overall progress of a transfer as reported by `rsync --info=progress2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub bytes: u64,
+    pub percent: u8,
+    /// throughput as formatted by rsync (e.g. "12.34MB/s")
+    pub rate: String,
+    /// estimated remaining time as formatted by rsync (e.g. "0:01:23")
+    pub eta: String,
+}
+
+impl TransferProgress {
+    /// parse a progress line (e.g. "  1,234,567  45%   12.34MB/s    0:01:23 (xfr#12, to-chk=100/200)"); `None` for other lines
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let bytes = fields.next()?;
+        let percent = fields.next()?.strip_suffix('%')?.parse().ok()?;
+        let rate = fields.next().filter(|r| r.ends_with("/s"))?;
+        let eta = fields
+            .next()
+            .filter(|e| e.split(':').all(|p| p.parse::<u32>().is_ok()))?;
+
+        // the thousands separator depends on the locale
+        let bytes = bytes
+            .chars()
+            .filter(|c| *c != ',' && *c != '.')
+            .collect::<String>()
+            .parse()
+            .ok()?;
+
+        Some(Self {
+            bytes,
+            percent,
+            rate: rate.to_string(),
+            eta: eta.to_string(),
+        })
+    }
+
+    fn format_text(&self, phase: Phase) -> String {
+        format!(
+            "{}: {}% ({}, {}, ETA {})",
+            phase.name(),
+            self.percent,
+            list::format_size(self.bytes),
+            self.rate,
+            self.eta
+        )
+    }
+}
+
+/// where the progress is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+    None,
+    /// a status line on stdout that is updated in place
+    Terminal,
+    /// periodic log lines
+    Log,
+}
+
+/// shows the phase of a run and the progress of the transfer
+pub struct Progress {
+    display: Display,
+    phase: Option<Phase>,
+    last_log: Option<Instant>,
+    /// whether the status line is shown on the terminal
+    line: bool,
+}
+
+impl Progress {
+    /// progress shown as a status line if stdout is a terminal and logged periodically otherwise; nothing is shown if disabled
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            display: match (enabled, std::io::stdout().is_terminal()) {
+                (false, _) => Display::None,
+                (true, true) => Display::Terminal,
+                (true, false) => Display::Log,
+            },
+            phase: None,
+            last_log: None,
+            line: false,
+        }
+    }
+
+    /// whether the progress of the transfer is shown (rsync only reports it if asked to)
+    pub fn enabled(&self) -> bool {
+        self.display != Display::None
+    }
+
+    pub fn phase(&mut self, phase: Phase) {
+        if self.phase == Some(phase) {
+            return;
+        }
+
+        self.phase = Some(phase);
+        self.last_log = None;
+
+        match self.display {
+            Display::None => {}
+            Display::Terminal => self.show(&format!("{}...", phase.name())),
+            Display::Log => log::info!("phase: {}", phase.name()),
+        }
+    }
+
+    pub fn transfer(&mut self, progress: &TransferProgress) {
+        let phase = self.phase.unwrap_or(Phase::Sync);
+
+        match self.display {
+            Display::None => {}
+            Display::Terminal => self.show(&progress.format_text(phase)),
+            Display::Log => match self.last_log {
+                Some(last_log) if last_log.elapsed() < LOG_INTERVAL => {}
+                _ => {
+                    log::info!("{}", progress.format_text(phase));
+                    self.last_log = Some(Instant::now());
+                }
+            },
+        }
+    }
+
+    /// end the status line, so that following output starts on a new line
+    pub fn finish(&mut self) {
+        if self.line {
+            println!();
+            self.line = false;
+        }
+    }
+
+    /// replace the status line
+    fn show(&mut self, text: &str) {
+        let mut stdout = std::io::stdout().lock();
+
+        // the progress is only informative, so errors writing it are ignored
+        let _ = write!(stdout, "\r\x1b[K{}", text);
+        let _ = stdout.flush();
+        self.line = true;
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            TransferProgress::parse(
+                "      1,234,567  45%   12.34MB/s    0:01:23 (xfr#12, to-chk=100/200)"
+            ),
+            Some(TransferProgress {
+                bytes: 1234567,
+                percent: 45,
+                rate: "12.34MB/s".to_string(),
+                eta: "0:01:23".to_string(),
+            })
+        );
+        assert_eq!(
+            TransferProgress::parse("              0   0%    0.00kB/s    0:00:00")
+                .map(|p| p.percent),
+            Some(0)
+        );
+        assert_eq!(
+            TransferProgress::parse("sending incremental file list"),
+            None
+        );
+        assert_eq!(TransferProgress::parse("home/user/100% done.txt"), None);
+        assert_eq!(
+            TransferProgress::parse("Number of files: 1,234 (reg: 1,000, dir: 234)"),
+            None
+        );
+    }
+
+    #[test]
+    fn format_text() {
+        let progress = TransferProgress {
+            bytes: 1610612736,
+            percent: 45,
+            rate: "12.34MB/s".to_string(),
+            eta: "0:01:23".to_string(),
+        };
+
+        assert_eq!(
+            progress.format_text(Phase::Sync),
+            "sync: 45% (1.5 GiB, 12.34MB/s, ETA 0:01:23)"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    config::Config, custom_duration::CustomDuration, metadata::SnapshotMetadata,
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// get the names of the snapshots to be removed according to the policy
+///
+/// Pinned snapshots and snapshots whose metadata (given in the same order as the snapshots) belongs to another job are never removed.
+pub fn snapshots_to_prune(
+    config: &Config,
+    date_time: &DateTime<Utc>,
+    snapshots: &[(DateTime<Utc>, String)],
+    metadata: &[Option<SnapshotMetadata>],
+) -> Result<Vec<String>, SyncError> {
+    let policy = config
+        .policy
+        .iter()
+        .map(|e| e.try_into())
+        .collect::<Result<Vec<Duration>, SyncError>>()?;
+    let unpinned = snapshots
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, name))| !config.pinned.contains(name))
+        .filter(|(idx, _)| other_job(config, metadata.get(*idx).and_then(|m| m.as_ref())).is_none())
+        .map(|(_, snapshot)| snapshot.clone())
+        .collect::<Vec<(DateTime<Utc>, String)>>();
+
+    Ok(policer::police(date_time, &policy[..], &unpinned[..])
+        .into_iter()
+        .map(|(_, name)| name.to_string())
+        .collect())
+}
+
+/// get the job that created a snapshot according to its metadata, if it is not the configured job
+pub fn other_job<'a>(config: &Config, metadata: Option<&'a SnapshotMetadata>) -> Option<&'a str> {
+    metadata
+        .map(|m| m.job.as_str())
+        .filter(|job| *job != config.job_name())
+}
+
+/// get the policy entry covering a snapshot of the given age, i.e. the shortest duration not exceeded by the age (or the longest duration)
+pub fn retention_rule(
+    policy: &[CustomDuration],
+    age: Duration,
+) -> Result<Option<&CustomDuration>, SyncError> {
+    let mut rules = policy
+        .iter()
+        .map(|cd| Ok((Duration::try_from(cd)?, cd)))
+        .collect::<Result<Vec<(Duration, &CustomDuration)>, SyncError>>()?;
+
+    rules.sort_by_key(|(duration, _)| *duration);
+
+    Ok(rules
+        .iter()
+        .find(|(duration, _)| age <= *duration)
+        .or_else(|| rules.last())
+        .map(|(_, cd)| *cd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_rule() {
+        let policy = vec![CustomDuration::days(2), CustomDuration::minutes(30)];
+
+        assert_eq!(
+            super::retention_rule(&policy, Duration::minutes(10))
+                .unwrap()
+                .map(|cd| cd.to_string()),
+            Some("30m".to_string())
+        );
+        assert_eq!(
+            super::retention_rule(&policy, Duration::hours(10))
+                .unwrap()
+                .map(|cd| cd.to_string()),
+            Some("2d".to_string())
+        );
+        assert_eq!(
+            super::retention_rule(&policy, Duration::weeks(10))
+                .unwrap()
+                .map(|cd| cd.to_string()),
+            Some("2d".to_string())
+        );
+        assert!(super::retention_rule(&[], Duration::weeks(10))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn other_job() {
+        use chrono::TimeZone;
+
+        let config = Config {
+            snapshot_suffix: "laptop".to_string(),
+            ..Default::default()
+        };
+        let metadata = |name: &str, job: &str| SnapshotMetadata {
+            name: name.to_string(),
+            job: job.to_string(),
+            source_host: "laptop".to_string(),
+            tool_version: "0.1.0".to_string(),
+            start: Utc.ymd(2022, 11, 1).and_hms(12, 0, 0),
+            end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+            exit_status: 0,
+            stats: None,
+            exclude_file_sha256: None,
+        };
+        let own = metadata("a", "laptop");
+        let other = metadata("b", "desktop");
+
+        assert_eq!(super::other_job(&config, Some(&own)), None);
+        assert_eq!(super::other_job(&config, Some(&other)), Some("desktop"));
+        assert_eq!(super::other_job(&config, None), None);
+    }
+}
@@ -0,0 +1,299 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/// options passed to rsync when syncing the backup; the defaults correspond to `--compress --one-file-system --delete-after --delete-excluded --partial-dir=.rsync-partial`
+#[derive(Debug, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RsyncOptions {
+    /// preserve ACLs (`--acls`)
+    pub acls: bool,
+    /// preserve extended attributes (`--xattrs`)
+    pub xattrs: bool,
+    /// preserve hard links (`--hard-links`)
+    pub hard_links: bool,
+    /// handle sparse files efficiently (`--sparse`)
+    pub sparse: bool,
+    /// do not map user and group ids by name (`--numeric-ids`)
+    pub numeric_ids: bool,
+    pub compress: bool,
+    pub compression_level: Option<u8>,
+    pub one_file_system: bool,
+    pub delete: DeleteMode,
+    /// also delete excluded files on the server (only if deleting is enabled)
+    pub delete_excluded: bool,
+    /// compare files by checksum instead of size and modification time (`--checksum`)
+    pub checksum: bool,
+    /// keep partially transferred files in this directory (relative to the destination), so an interrupted transfer can be resumed; `null` discards them (not used with `--inplace` or `--append` in `extra_args`, which rsync does not combine with a partial directory)
+    pub partial_dir: Option<String>,
+    /// additional arguments appended to the options above
+    pub extra_args: Vec<RsyncArg>,
+}
+
+/// directory of partially transferred files used by default
+const DEFAULT_PARTIAL_DIR: &str = ".rsync-partial";
+
+impl Default for RsyncOptions {
+    fn default() -> Self {
+        Self {
+            acls: false,
+            xattrs: false,
+            hard_links: false,
+            sparse: false,
+            numeric_ids: false,
+            compress: true,
+            compression_level: None,
+            one_file_system: true,
+            delete: DeleteMode::After,
+            delete_excluded: true,
+            checksum: false,
+            partial_dir: Some(DEFAULT_PARTIAL_DIR.to_string()),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+/// when files missing in the source are deleted on the server
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    /// never delete files on the server
+    None,
+    Before,
+    During,
+    Delay,
+    #[default]
+    After,
+}
+
+/// additional argument for rsync; must be an option and must not interfere with the arguments set by this tool
+#[derive(Debug, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct RsyncArg(String);
+
+/// options controlled by this tool or changing the meaning of the transfer (short options are rejected if they contain one of `FORBIDDEN_SHORT_ARGS`)
+const FORBIDDEN_ARGS: [&str; 24] = [
+    "-e",
+    "--rsh",
+    "--rsync-path",
+    "--remote-option",
+    "--partial-dir",
+    "--del",
+    "--log-file",
+    "--exclude-from",
+    "--files-from",
+    "--remove-source-files",
+    "--remove-sent-files",
+    "--server",
+    "--sender",
+    "--daemon",
+    "--dry-run",
+    "--only-write-batch",
+    "--write-batch",
+    "--bwlimit",
+    "--time-limit",
+    "--stop-after",
+    "--stop-at",
+    "--info",
+    "--out-format",
+    "--stats",
+];
+
+/// short options controlled by this tool or changing the meaning of the transfer (`-e` remote shell, `-n` dry run, `-M` remote option)
+const FORBIDDEN_SHORT_ARGS: [char; 3] = ['e', 'n', 'M'];
+
+/// prefix of the deletion options, which are set with `delete`
+const DELETE_ARGS: &str = "--delete";
+
+/// options writing into the destination files directly; rsync rejects them together with `--partial-dir`
+const IN_PLACE_ARGS: [&str; 3] = ["--inplace", "--append", "--append-verify"];
+
+impl RsyncArg {
+    /// name of the option without its value
+    fn name(&self) -> &str {
+        self.0.split('=').next().unwrap_or_default()
+    }
+}
+
+impl TryFrom<String> for RsyncArg {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let name = value.split('=').next().unwrap_or_default();
+
+        if !value.starts_with('-') || value.chars().any(char::is_whitespace) {
+            return Err(format!("invalid rsync argument \"{}\"", value));
+        }
+        if FORBIDDEN_ARGS.contains(&name)
+            || name.starts_with(DELETE_ARGS)
+            || (!name.starts_with("--") && name.contains(FORBIDDEN_SHORT_ARGS))
+        {
+            return Err(format!("rsync argument \"{}\" is not allowed", value));
+        }
+
+        Ok(RsyncArg(value))
+    }
+}
+
+impl RsyncOptions {
+    /// partial directory passed to rsync; not used with the options writing into the destination files directly
+    pub fn effective_partial_dir(&self) -> Option<&str> {
+        let in_place = self
+            .extra_args
+            .iter()
+            .any(|a| IN_PLACE_ARGS.contains(&a.name()));
+
+        self.partial_dir.as_deref().filter(|_| !in_place)
+    }
+
+    /// arguments preceding the exclude file
+    pub fn transfer_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if self.compress {
+            args.push("--compress".to_string());
+
+            if let Some(level) = self.compression_level {
+                args.push(format!("--compress-level={}", level));
+            }
+        }
+        if self.one_file_system {
+            args.push("--one-file-system".to_string());
+        }
+
+        args
+    }
+
+    /// arguments following the exclude file
+    pub fn file_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let delete = match self.delete {
+            DeleteMode::None => None,
+            DeleteMode::Before => Some("--delete-before"),
+            DeleteMode::During => Some("--delete-during"),
+            DeleteMode::Delay => Some("--delete-delay"),
+            DeleteMode::After => Some("--delete-after"),
+        };
+
+        if let Some(delete) = delete {
+            args.push(delete.to_string());
+
+            if self.delete_excluded {
+                args.push("--delete-excluded".to_string());
+            }
+        }
+
+        for (enabled, arg) in [
+            (self.acls, "--acls"),
+            (self.xattrs, "--xattrs"),
+            (self.hard_links, "--hard-links"),
+            (self.sparse, "--sparse"),
+            (self.numeric_ids, "--numeric-ids"),
+            (self.checksum, "--checksum"),
+        ] {
+            if enabled {
+                args.push(arg.to_string());
+            }
+        }
+
+        if let Some(dir) = self.effective_partial_dir() {
+            args.push(format!("--partial-dir={}", dir));
+        }
+
+        args.extend(self.extra_args.iter().map(|a| a.0.clone()));
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize() {
+        let options: RsyncOptions = serde_json::from_str(
+            r#"{"acls": true, "compress": false, "delete": "none", "extra_args": ["--inplace", "--max-size=1G"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            options,
+            RsyncOptions {
+                acls: true,
+                compress: false,
+                delete: DeleteMode::None,
+                extra_args: vec![
+                    RsyncArg("--inplace".to_string()),
+                    RsyncArg("--max-size=1G".to_string())
+                ],
+                ..Default::default()
+            }
+        );
+        assert_eq!(options.transfer_args(), vec!["--one-file-system"]);
+        assert_eq!(
+            options.file_args(),
+            vec!["--acls", "--inplace", "--max-size=1G"]
+        );
+    }
+
+    #[test]
+    fn partial_dir() {
+        let options = RsyncOptions::default();
+
+        assert!(options
+            .file_args()
+            .contains(&"--partial-dir=.rsync-partial".to_string()));
+        assert_eq!(options.effective_partial_dir(), Some(".rsync-partial"));
+
+        for arg in ["--inplace", "--append", "--append-verify"] {
+            let options = RsyncOptions {
+                extra_args: vec![RsyncArg::try_from(arg.to_string()).unwrap()],
+                ..Default::default()
+            };
+
+            assert_eq!(
+                options.file_args(),
+                vec!["--delete-after", "--delete-excluded", arg]
+            );
+            assert_eq!(options.effective_partial_dir(), None);
+        }
+    }
+
+    #[test]
+    fn invalid_extra_args() {
+        for arg in [
+            "inplace",
+            "--rsh=nc",
+            "-e",
+            "-ve",
+            "-n",
+            "--log-file=/tmp/x",
+            "--dry-run",
+            "--exclude x",
+            "--rsync-path=sudo rsync",
+            "--rsync-path=/tmp/rsync",
+            "-M--fake-super",
+            "-aM",
+            "--remote-option=--log-file=/tmp/x",
+            "--remove-sent-files",
+            "--partial-dir=/tmp",
+            "--del",
+            "--delete",
+            "--delete-before",
+            "--delete-excluded",
+            "--delete-missing-args",
+            "--bwlimit=1M",
+            "--time-limit=10",
+            "--stop-after=10",
+            "--stop-at=23:00",
+            "--info=progress2",
+            "--out-format=%n",
+            "--stats",
+        ] {
+            assert!(RsyncArg::try_from(arg.to_string()).is_err(), "{}", arg);
+        }
+        assert!(RsyncArg::try_from("-z".to_string()).is_ok());
+        assert!(RsyncArg::try_from("--max-delete=100".to_string()).is_ok());
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// transfer statistics reported by `rsync --stats`
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RsyncStats {
+    pub files: Option<u64>,
+    pub files_transferred: Option<u64>,
+    pub total_file_size: Option<u64>,
+    pub transferred_file_size: Option<u64>,
+    pub bytes_sent: Option<u64>,
+    pub bytes_received: Option<u64>,
+}
+
+impl RsyncStats {
+    /// parse the statistics from the output of rsync
+    pub fn parse(output: &str) -> Self {
+        let mut stats = RsyncStats::default();
+
+        for line in output.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), parse_number(value)),
+                None => continue,
+            };
+
+            match key {
+                "Number of files" => stats.files = value,
+                "Number of regular files transferred" | "Number of files transferred" => {
+                    stats.files_transferred = value
+                }
+                "Total file size" => stats.total_file_size = value,
+                "Total transferred file size" => stats.transferred_file_size = value,
+                "Total bytes sent" => stats.bytes_sent = value,
+                "Total bytes received" => stats.bytes_received = value,
+                _ => {}
+            }
+        }
+
+        stats
+    }
+}
+
+/// parse the first number of a value, ignoring digit grouping (e.g. "1,234 bytes")
+fn parse_number(value: &str) -> Option<u64> {
+    value
+        .split_whitespace()
+        .next()?
+        .chars()
+        .filter(|c| !matches!(c, ',' | '.' | '\''))
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let output = "sending incremental file list
+./
+file.txt
+
+Number of files: 3 (reg: 2, dir: 1)
+Number of created files: 1 (reg: 1)
+Number of deleted files: 0
+Number of regular files transferred: 1
+Total file size: 1,234,567 bytes
+Total transferred file size: 4,096 bytes
+Literal data: 4,096 bytes
+Matched data: 0 bytes
+File list size: 0
+Total bytes sent: 4,321
+Total bytes received: 35
+
+sent 4,321 bytes  received 35 bytes  8,712.00 bytes/sec
+total size is 1,234,567  speedup is 283.42
+";
+
+        assert_eq!(
+            RsyncStats::parse(output),
+            RsyncStats {
+                files: Some(3),
+                files_transferred: Some(1),
+                total_file_size: Some(1234567),
+                transferred_file_size: Some(4096),
+                bytes_sent: Some(4321),
+                bytes_received: Some(35),
+            }
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use crate::{clock::SnapshotTimeZone, custom_duration::CustomDuration, sync_error::SyncError};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/// runs older than this are considered missed rather than due
+const MISSED_AFTER: i64 = 5;
+/// how far ahead the next time matching a cron expression is searched
+const SEARCH_YEARS: i64 = 5;
+
+/// when the daemon runs a job
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Schedule {
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub missed: MissedRuns,
+    /// do not run while the host is on battery
+    #[serde(default)]
+    pub skip_on_battery: bool,
+    /// do not run while the network connection is metered
+    #[serde(default)]
+    pub skip_on_metered: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// run the job this long after the last run
+    Interval(CustomDuration),
+    /// run the job at the times matching the cron expression; evaluated in the time zone of the job
+    Cron(CronExpr),
+}
+
+impl JsonSchema for Trigger {
+    fn schema_name() -> String {
+        "Trigger".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // the trigger is flattened into the schedule, so the variants must not forbid the other fields
+        let variant = |name: &str, schema: Schema| {
+            let mut variant = SchemaObject {
+                instance_type: Some(InstanceType::Object.into()),
+                ..Default::default()
+            };
+
+            variant.object().required.insert(name.to_string());
+            variant.object().properties.insert(name.to_string(), schema);
+            Schema::Object(variant)
+        };
+
+        Schema::Object(SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(vec![
+                    variant("interval", gen.subschema_for::<CustomDuration>()),
+                    variant("cron", gen.subschema_for::<CronExpr>()),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+}
+
+/// what happens to runs missed while the daemon was not running or the host was suspended
+#[derive(Debug, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedRuns {
+    /// run the job as soon as possible
+    #[default]
+    CatchUp,
+    /// wait for the next scheduled time
+    Skip,
+}
+
+impl Schedule {
+    /// get the time of the next run given the start of the last run; the run is due if the time is not after now
+    ///
+    /// Without a previous run, the job is scheduled from `since` (e.g. the start of the daemon), which must stay fixed between calls.
+    pub fn next_run(
+        &self,
+        last: Option<DateTime<Utc>>,
+        since: &DateTime<Utc>,
+        now: &DateTime<Utc>,
+        time_zone: SnapshotTimeZone,
+    ) -> Result<DateTime<Utc>, SyncError> {
+        let missed_before = *now - Duration::minutes(MISSED_AFTER);
+
+        match &self.trigger {
+            Trigger::Interval(interval) => {
+                let interval = Duration::try_from(interval)?;
+
+                if interval <= Duration::zero() {
+                    return Err(SyncError::InvalidSchedule(format!(
+                        "interval {} is not positive",
+                        interval
+                    )));
+                }
+
+                let next = match last {
+                    Some(last) => last + interval,
+                    None => return Ok(*since),
+                };
+
+                match (self.missed, next < missed_before) {
+                    (MissedRuns::Skip, true) => {
+                        let missed = (missed_before - next).num_seconds() / interval.num_seconds();
+
+                        Ok(next + interval * (missed as i32 + 1))
+                    }
+                    _ => Ok(next),
+                }
+            }
+            Trigger::Cron(cron) => {
+                // without a previous run, the job waits for the next matching time
+                let next = cron.next_after(last.as_ref().unwrap_or(since), time_zone)?;
+
+                match (self.missed, next < missed_before) {
+                    (MissedRuns::Skip, true) => cron.next_after(&missed_before, time_zone),
+                    _ => Ok(next),
+                }
+            }
+        }
+    }
+}
+
+/// cron expression with the fields minute, hour, day of month, month and day of week
+///
+/// Fields support "*", values, ranges ("1-5"), steps ("*/15") and lists ("1,15"). As in cron, a time matches if the day of month or the day of week matches when both are restricted.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl TryFrom<String> for CronExpr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let fields = value.split_whitespace().collect::<Vec<&str>>();
+
+        if fields.len() != 5 {
+            return Err(format!(
+                "invalid cron expression \"{}\" (expected 5 fields)",
+                value
+            ));
+        }
+
+        let field = |idx: usize, min: u32, max: u32| {
+            parse_field(fields[idx], min, max)
+                .map_err(|e| format!("invalid cron expression \"{}\" ({})", value, e))
+        };
+        let mut weekdays = field(4, 0, 7)?;
+
+        // both 0 and 7 denote Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(CronExpr {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl JsonSchema for CronExpr {
+    fn schema_name() -> String {
+        "CronExpr".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// parse a field of a cron expression into a bit set of the matching values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut res = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .map_err(|_| format!("invalid step \"{}\"", step))?,
+            ),
+            None => (part, 1),
+        };
+        let value = |v: &str| {
+            v.parse::<u32>()
+                .ok()
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| format!("invalid value \"{}\"", v))
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // a single value with a step starts a range
+            None if part.contains('/') => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+
+        if step == 0 || from > to {
+            return Err(format!("invalid range \"{}\"", part));
+        }
+
+        for v in (from..=to).step_by(step as usize) {
+            res |= 1 << v;
+        }
+    }
+
+    Ok(res)
+}
+
+impl CronExpr {
+    /// get the first matching time after the given time
+    pub fn next_after(
+        &self,
+        after: &DateTime<Utc>,
+        time_zone: SnapshotTimeZone,
+    ) -> Result<DateTime<Utc>, SyncError> {
+        let mut after = time_zone.convert(after).naive_local();
+
+        // times skipped by a change to daylight saving time cannot be converted
+        loop {
+            let next = self.next_after_local(&after).ok_or_else(|| {
+                SyncError::InvalidSchedule("cron expression never matches".to_string())
+            })?;
+
+            match time_zone.from_local(&next) {
+                Some(next) => return Ok(next),
+                None => after = next,
+            }
+        }
+    }
+
+    fn next_after_local(&self, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.date().and_hms(after.hour(), after.minute(), 0) + Duration::minutes(1);
+        let end = start + Duration::days(366 * SEARCH_YEARS);
+        let mut time = start;
+
+        while time < end {
+            let date = time.date();
+
+            if !matches(self.months, date.month()) {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+
+                time = NaiveDate::from_ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.matches_day(&date) {
+                time = date.succ().and_hms(0, 0, 0);
+            } else if !matches(self.hours, time.hour()) {
+                time = date.and_hms(time.hour(), 0, 0) + Duration::hours(1);
+            } else if !matches(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+
+        None
+    }
+
+    fn matches_day(&self, date: &NaiveDate) -> bool {
+        let day = matches(self.days, date.day());
+        let weekday = matches(self.weekdays, date.weekday().num_days_from_sunday());
+
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn matches(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+impl CronExpr {
+    /// get the equivalent calendar events for systemd timers (`OnCalendar`)
+    ///
+    /// systemd requires both the day of month and the day of week to match, so two events are needed if both are restricted.
+    pub fn calendar_events(&self) -> Vec<String> {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        let weekdays = (0..7)
+            .filter(|d| matches(self.weekdays, *d))
+            .map(|d| WEEKDAYS[d as usize])
+            .collect::<Vec<&str>>()
+            .join(",");
+        let months = calendar_values(self.months, 1, 12);
+        let days = calendar_values(self.days, 1, 31);
+        let time = format!(
+            "{}:{}:00",
+            calendar_values(self.hours, 0, 23),
+            calendar_values(self.minutes, 0, 59)
+        );
+
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => vec![
+                format!("*-{}-{} {}", months, days, time),
+                format!("{} *-{}-* {}", weekdays, months, time),
+            ],
+            (_, true) => vec![format!("{} *-{}-{} {}", weekdays, months, days, time)],
+            _ => vec![format!("*-{}-{} {}", months, days, time)],
+        }
+    }
+}
+
+/// format the values of a bit set as a list for systemd; "*" if all values are set
+fn calendar_values(set: u64, min: u32, max: u32) -> String {
+    match (min..=max).all(|v| matches(set, v)) {
+        true => "*".to_string(),
+        false => (min..=max)
+            .filter(|v| matches(set, *v))
+            .map(|v| format!("{:02}", v))
+            .collect::<Vec<String>>()
+            .join(","),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn cron(expr: &str) -> CronExpr {
+        CronExpr::try_from(expr.to_string()).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronExpr::try_from(expr.to_string()).is_err(), "{}", expr);
+        }
+
+        let expr = cron("*/15 2,14 1-7 * 7");
+
+        assert_eq!(expr.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(expr.hours, 1 << 2 | 1 << 14);
+        assert_eq!(expr.weekdays, 1 | 1 << 7);
+    }
+
+    #[test]
+    fn next_after() {
+        let after = Utc.ymd(2022, 11, 1).and_hms(12, 0, 30);
+
+        assert_eq!(
+            cron("30 2 * * *")
+                .next_after(&after, SnapshotTimeZone::Utc)
+                .unwrap(),
+            Utc.ymd(2022, 11, 2).and_hms(2, 30, 0)
+        );
+        // the first Sunday of December or any day from the 1st to the 7th
+        assert_eq!(
+            cron("0 0 1-7 12 0")
+                .next_after(&after, SnapshotTimeZone::Utc)
+                .unwrap(),
+            Utc.ymd(2022, 12, 1).and_hms(0, 0, 0)
+        );
+        // Mondays only
+        assert_eq!(
+            cron("0 9 * * 1")
+                .next_after(&after, SnapshotTimeZone::Utc)
+                .unwrap(),
+            Utc.ymd(2022, 11, 7).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            cron("0 3 * * *")
+                .next_after(
+                    &after,
+                    SnapshotTimeZone::try_from("+02:00".to_string()).unwrap()
+                )
+                .unwrap(),
+            Utc.ymd(2022, 11, 2).and_hms(1, 0, 0)
+        );
+        assert!(cron("0 0 31 2 *")
+            .next_after(&after, SnapshotTimeZone::Utc)
+            .is_err());
+    }
+
+    #[test]
+    fn calendar_events() {
+        assert_eq!(cron("30 2 * * *").calendar_events(), vec!["*-*-* 02:30:00"]);
+        assert_eq!(
+            cron("*/20 8-9 * * 1-5").calendar_events(),
+            vec!["Mon,Tue,Wed,Thu,Fri *-*-* 08,09:00,20,40:00"]
+        );
+        assert_eq!(
+            cron("0 0 1 * 0").calendar_events(),
+            vec!["*-*-01 00:00:00", "Sun *-*-* 00:00:00"]
+        );
+    }
+
+    #[test]
+    fn next_run() {
+        let now = Utc.ymd(2022, 11, 3).and_hms(8, 0, 0);
+        let last = Some(Utc.ymd(2022, 11, 1).and_hms(2, 30, 0));
+        let schedule = |trigger, missed| Schedule {
+            trigger,
+            missed,
+            skip_on_battery: false,
+            skip_on_metered: false,
+        };
+        let interval = |missed| schedule(Trigger::Interval(CustomDuration::hours(6)), missed);
+        let daily = |missed| schedule(Trigger::Cron(cron("30 2 * * *")), missed);
+        let next_run = |schedule: Schedule, last| {
+            schedule
+                .next_run(last, &now, &now, SnapshotTimeZone::Utc)
+                .unwrap()
+        };
+
+        assert_eq!(next_run(interval(MissedRuns::CatchUp), None), now);
+        assert_eq!(
+            next_run(interval(MissedRuns::CatchUp), last),
+            Utc.ymd(2022, 11, 1).and_hms(8, 30, 0)
+        );
+        assert_eq!(
+            next_run(interval(MissedRuns::Skip), last),
+            Utc.ymd(2022, 11, 3).and_hms(8, 30, 0)
+        );
+        assert_eq!(
+            next_run(daily(MissedRuns::CatchUp), None),
+            Utc.ymd(2022, 11, 4).and_hms(2, 30, 0)
+        );
+        assert_eq!(
+            next_run(daily(MissedRuns::CatchUp), last),
+            Utc.ymd(2022, 11, 2).and_hms(2, 30, 0)
+        );
+        assert_eq!(
+            next_run(daily(MissedRuns::Skip), last),
+            Utc.ymd(2022, 11, 4).and_hms(2, 30, 0)
+        );
+        // the first run of a job without a previous run is due once the time has passed
+        assert_eq!(
+            daily(MissedRuns::CatchUp)
+                .next_run(
+                    None,
+                    &Utc.ymd(2022, 11, 3).and_hms(2, 0, 0),
+                    &now,
+                    SnapshotTimeZone::Utc
+                )
+                .unwrap(),
+            Utc.ymd(2022, 11, 3).and_hms(2, 30, 0)
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let schedule: Schedule = serde_json::from_str(
+            r#"{"cron": "0 3 * * *", "missed": "skip", "skip_on_battery": true}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(schedule.trigger, Trigger::Cron(_)));
+        assert_eq!(schedule.missed, MissedRuns::Skip);
+        assert!(schedule.skip_on_battery);
+
+        let schedule: Schedule = serde_json::from_str(r#"{"interval": {"hours": 6}}"#).unwrap();
+
+        assert!(matches!(schedule.trigger, Trigger::Interval(_)));
+        assert_eq!(schedule.missed, MissedRuns::CatchUp);
+    }
+}
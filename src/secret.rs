@@ -0,0 +1,91 @@
+use crate::sync_error::SyncError;
+use serde_json::Value;
+use std::fs;
+
+/// prefix of strings read from an environment variable (e.g. "env:BACKUP_HOST")
+const ENV_PREFIX: &str = "env:";
+/// prefix of strings read from a file (e.g. "file:/run/secrets/smtp_password")
+const FILE_PREFIX: &str = "file:";
+
+/// replace all references to environment variables and files among the strings of the configuration by their values
+pub fn resolve(value: &mut Value) -> Result<(), SyncError> {
+    match value {
+        Value::String(s) => {
+            if let Some(resolved) = resolve_str(s)? {
+                *s = resolved;
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                resolve(value)?;
+            }
+        }
+        Value::Object(values) => {
+            for value in values.values_mut() {
+                resolve(value)?;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+
+    Ok(())
+}
+
+/// get the value of a reference; `None` if the string is not a reference
+fn resolve_str(s: &str) -> Result<Option<String>, SyncError> {
+    if let Some(name) = s.strip_prefix(ENV_PREFIX) {
+        return std::env::var(name)
+            .map(Some)
+            .map_err(|e| SyncError::SecretError(s.to_string(), e.to_string()));
+    }
+
+    if let Some(path) = s.strip_prefix(FILE_PREFIX) {
+        let content = fs::read_to_string(path)
+            .map_err(|e| SyncError::SecretError(s.to_string(), e.to_string()))?;
+
+        // files written by editors and secret stores usually end with a newline
+        return Ok(Some(
+            content
+                .strip_suffix('\n')
+                .map(|c| c.strip_suffix('\r').unwrap_or(c))
+                .unwrap_or(&content)
+                .to_string(),
+        ));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn resolve() {
+        let path = std::env::temp_dir().join(format!("backup-rsync-secret-{}", std::process::id()));
+
+        fs::write(&path, "secret\n").unwrap();
+        std::env::set_var("BACKUP_RSYNC_TEST_HOST", "backup.example.com");
+
+        let mut value = json!({
+            "ssh_credentials": {"user": "backup", "host": "env:BACKUP_RSYNC_TEST_HOST"},
+            "notifications": [{"sink": {"password": format!("file:{}", path.to_string_lossy())}}],
+            "manifest": true
+        });
+
+        super::resolve(&mut value).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "ssh_credentials": {"user": "backup", "host": "backup.example.com"},
+                "notifications": [{"sink": {"password": "secret"}}],
+                "manifest": true
+            })
+        );
+        assert!(super::resolve(&mut json!(["env:BACKUP_RSYNC_TEST_MISSING"])).is_err());
+        assert!(super::resolve(&mut json!({"password": "file:/nonexistent/secret"})).is_err());
+    }
+}
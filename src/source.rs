@@ -0,0 +1,176 @@
+use crate::sync_error::SyncError;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// paths backed up by a job
+#[derive(Debug, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Source {
+    /// directory whose content is synced into the destination
+    Single(String),
+    /// absolute paths synced with `rsync --relative`, so that their full path is preserved in the destination
+    Multiple(Vec<String>),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Single(String::new())
+    }
+}
+
+impl From<&str> for Source {
+    fn from(source: &str) -> Self {
+        Source::Single(source.to_string())
+    }
+}
+
+impl Source {
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            Source::Single(path) => vec![path.as_str()],
+            Source::Multiple(paths) => paths.iter().map(|p| p.as_str()).collect(),
+        }
+    }
+
+    /// rsync options required by the source
+    pub fn options(&self) -> Vec<&str> {
+        match self {
+            Source::Single(_) => Vec::new(),
+            Source::Multiple(_) => vec!["--relative"],
+        }
+    }
+
+    /// check that multiple sources are absolute and do not overlap
+    pub fn validate(&self) -> Result<(), SyncError> {
+        let paths = match self {
+            Source::Single(_) => return Ok(()),
+            Source::Multiple(paths) => paths,
+        };
+
+        if paths.is_empty() {
+            return Err(SyncError::InvalidSource("no source path".to_string()));
+        }
+
+        for (idx, path) in paths.iter().enumerate() {
+            if !Path::new(path).is_absolute() {
+                return Err(SyncError::InvalidSource(format!(
+                    "\"{}\" is not an absolute path",
+                    path
+                )));
+            }
+
+            if let Some(other) = paths
+                .iter()
+                .enumerate()
+                .find(|(other_idx, other)| {
+                    *other_idx != idx && Path::new(path).starts_with(Path::new(other))
+                })
+                .map(|(_, other)| other)
+            {
+                return Err(SyncError::InvalidSource(format!(
+                    "\"{}\" overlaps with \"{}\"",
+                    path, other
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// get the mount points below the source paths; their content is skipped by `rsync --one-file-system`
+    pub fn nested_mounts(&self, mounts: &str) -> Vec<String> {
+        let paths = self.paths();
+
+        mounts
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .map(unescape_mount_point)
+            .filter(|mount| {
+                paths.iter().any(|path| {
+                    Path::new(mount) != Path::new(path) && Path::new(mount).starts_with(path)
+                })
+            })
+            .collect()
+    }
+
+    /// log a warning for every mount point below the source paths
+    pub fn warn_nested_mounts(&self) {
+        match fs::read_to_string("/proc/self/mounts") {
+            Ok(mounts) => {
+                for mount in self.nested_mounts(&mounts) {
+                    log::warn!(
+                        "\"{}\" is on a different file system and is not backed up with \"one_file_system\"",
+                        mount
+                    );
+                }
+            }
+            Err(e) => log::debug!("could not read the mount points: {}", e),
+        }
+    }
+}
+
+/// decode the octal escapes (e.g. "\040" for a space) used in the mount table
+fn unescape_mount_point(mount: &str) -> String {
+    let mut res = String::with_capacity(mount.len());
+    let mut chars = mount.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code = chars.clone().take(3).collect::<String>();
+
+            if let Ok(value) = u8::from_str_radix(&code, 8) {
+                res.push(value as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+
+        res.push(c);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize() {
+        assert_eq!(
+            serde_json::from_str::<Source>(r#""/home""#).unwrap(),
+            Source::Single("/home".to_string())
+        );
+
+        let source = serde_json::from_str::<Source>(r#"["/home", "/etc"]"#).unwrap();
+
+        assert_eq!(source.options(), vec!["--relative"]);
+        assert_eq!(source.paths(), vec!["/home", "/etc"]);
+    }
+
+    #[test]
+    fn validate() {
+        let source =
+            |paths: &[&str]| Source::Multiple(paths.iter().map(|p| p.to_string()).collect());
+
+        assert!(source(&["/home", "/etc", "/var/lib/db"]).validate().is_ok());
+        assert!(source(&["/home", "/home/user"]).validate().is_err());
+        assert!(source(&["/var/lib", "/var/lib/"]).validate().is_err());
+        assert!(source(&["/home", "etc"]).validate().is_err());
+        assert!(source(&[]).validate().is_err());
+        assert!(source(&["/home", "/homes"]).validate().is_ok());
+    }
+
+    #[test]
+    fn nested_mounts() {
+        let mounts = "/dev/sda1 / ext4 rw,relatime 0 0
+/dev/sda2 /home ext4 rw,relatime 0 0
+/dev/sdb1 /home/user/my\\040disk vfat rw 0 0
+tmpfs /tmp tmpfs rw 0 0
+";
+        let source = Source::Multiple(vec!["/home".to_string(), "/etc".to_string()]);
+
+        assert_eq!(source.nested_mounts(mounts), vec!["/home/user/my disk"]);
+    }
+}
@@ -1,8 +1,81 @@
+use crate::{commands::shell_quote, custom_duration::CustomDuration};
+use schemars::JsonSchema;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Default)]
 pub struct SshCredentials {
     pub user: String,
+    /// identity file; may be the public key if the private key is held by the ssh agent
     pub id_file: String,
     pub host: String,
+    /// socket of the ssh agent holding the (passphrase-protected) key (see `IdentityAgent` in ssh_config)
+    pub identity_agent: Option<String>,
+    /// maximum duration of the commands run on the other host via ssh (not applied to transfers)
+    pub timeout: Option<CustomDuration>,
+}
+
+impl SshCredentials {
+    /// options of ssh selecting the user and the key
+    pub fn ssh_options(&self) -> Vec<String> {
+        let mut options = vec![
+            "-l".to_string(),
+            self.user.clone(),
+            "-i".to_string(),
+            self.id_file.clone(),
+        ];
+
+        if let Some(agent) = &self.identity_agent {
+            options.push("-o".to_string());
+            options.push(format!("IdentityAgent={}", agent));
+        }
+
+        options
+    }
+
+    /// ssh command for the remote shell of rsync (`rsync -e`)
+    pub fn ssh_command(&self) -> String {
+        std::iter::once("ssh".to_string())
+            .chain(
+                self.ssh_options()
+                    .iter()
+                    .map(|o| shell_quote(o).to_string()),
+            )
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+impl SshCredentials {
+    /// credentials used by the tests
+    pub fn test(user: &str, id_file: &str, host: &str) -> Self {
+        SshCredentials {
+            user: user.to_string(),
+            id_file: id_file.to_string(),
+            host: host.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_command() {
+        let mut creds = SshCredentials::test("user", "/home/user/.ssh/id_ed25519.pub", "host");
+
+        assert_eq!(
+            creds.ssh_command(),
+            "ssh -l user -i /home/user/.ssh/id_ed25519.pub"
+        );
+
+        creds.identity_agent = Some("/run/user/1000/ssh agent.socket".to_string());
+
+        assert_eq!(
+            creds.ssh_command(),
+            "ssh -l user -i /home/user/.ssh/id_ed25519.pub -o 'IdentityAgent=/run/user/1000/ssh agent.socket'"
+        );
+    }
 }
@@ -0,0 +1,181 @@
+use crate::{
+    clock::SnapshotTimeZone, file_util, list, outcome::RunOutcome, outcome::RunStatus,
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind, path::Path};
+
+/// state of a job persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct JobState {
+    pub last_outcome: Option<RunOutcome>,
+    pub last_success: Option<DateTime<Utc>>,
+    /// start of a run interrupted before creating its snapshot; the next run resumes the transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<DateTime<Utc>>,
+}
+
+impl JobState {
+    /// read the state file; a missing file yields the default state
+    pub fn read(path: &Path) -> Result<Self, SyncError> {
+        match fs::read(path) {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(JobState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SyncError> {
+        file_util::write_atomic(path, &serde_json::to_vec_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// record the outcome of a run
+    pub fn update(&mut self, outcome: &RunOutcome) {
+        if outcome.status == RunStatus::Success {
+            self.last_success = Some(outcome.end);
+        }
+
+        match (&outcome.snapshot, outcome.status) {
+            (Some(_), _) => self.interrupted = None,
+            // a resumed run that is interrupted again keeps the start of the first run
+            (None, RunStatus::Interrupted) if self.interrupted.is_none() => {
+                self.interrupted = Some(outcome.start)
+            }
+            _ => {}
+        }
+
+        self.last_outcome = Some(outcome.clone());
+    }
+
+    /// format the last run and the last success as text
+    pub fn format_text(&self, time_zone: SnapshotTimeZone) -> String {
+        let date = |d: &DateTime<Utc>| {
+            time_zone
+                .convert(d)
+                .format("%Y-%m-%d %H:%M %:z")
+                .to_string()
+        };
+        let mut res = String::new();
+
+        match &self.last_outcome {
+            Some(outcome) => {
+                res.push_str(&format!("job:          {}\n", outcome.job));
+                res.push_str(&format!(
+                    "last run:     {} ({})\n",
+                    date(&outcome.start),
+                    outcome.status.name()
+                ));
+                res.push_str(&format!(
+                    "duration:     {}\n",
+                    list::format_age(outcome.end - outcome.start)
+                ));
+
+                if let Some(snapshot) = &outcome.snapshot {
+                    res.push_str(&format!("snapshot:     {}\n", snapshot));
+                }
+                if let Some(error) = &outcome.error {
+                    res.push_str(&format!("error:        {}\n", error));
+                }
+            }
+            None => res.push_str("last run:     never\n"),
+        }
+
+        res.push_str(&format!(
+            "last success: {}\n",
+            self.last_success
+                .as_ref()
+                .map(date)
+                .unwrap_or_else(|| "never".to_string())
+        ));
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn update_and_persist() {
+        let path = std::env::temp_dir().join(format!(
+            "backup-rsync-state-{}/job.json",
+            std::process::id()
+        ));
+        let mut state = JobState::read(&path).unwrap();
+
+        assert_eq!(state, JobState::default());
+
+        let outcome = RunOutcome {
+            status: RunStatus::Success,
+            end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+            snapshot: Some("snapshot/2022-11-01T12:00:00Z_test".to_string()),
+            ..RunOutcome::new("job", Utc.ymd(2022, 11, 1).and_hms(12, 0, 0))
+        };
+
+        state.update(&RunOutcome {
+            status: RunStatus::Interrupted,
+            snapshot: None,
+            ..outcome.clone()
+        });
+
+        assert_eq!(state.interrupted, Some(outcome.start));
+
+        state.update(&outcome);
+
+        assert_eq!(state.interrupted, None);
+
+        state.update(&RunOutcome {
+            status: RunStatus::Failure,
+            snapshot: None,
+            error: Some("error".to_string()),
+            ..outcome.clone()
+        });
+        state.write(&path).unwrap();
+
+        let state = JobState::read(&path).unwrap();
+
+        assert_eq!(
+            state.last_success,
+            Some(Utc.ymd(2022, 11, 1).and_hms(12, 5, 0))
+        );
+        assert_eq!(
+            state.last_outcome.map(|o| o.status),
+            Some(RunStatus::Failure)
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn format_text() {
+        let start = Utc.ymd(2022, 11, 1).and_hms(12, 0, 0);
+        let state = JobState {
+            last_outcome: Some(RunOutcome {
+                status: RunStatus::Failure,
+                end: Utc.ymd(2022, 11, 1).and_hms(12, 5, 0),
+                error: Some("error".to_string()),
+                ..RunOutcome::new("job", start)
+            }),
+            last_success: None,
+            interrupted: None,
+        };
+
+        assert_eq!(
+            state.format_text(SnapshotTimeZone::Utc),
+            "job:          job
+last run:     2022-11-01 12:00 +00:00 (failure)
+duration:     5m
+error:        error
+last success: never
+"
+        );
+        assert_eq!(
+            JobState::default().format_text(SnapshotTimeZone::Utc),
+            "last run:     never\nlast success: never\n"
+        );
+    }
+}
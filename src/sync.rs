@@ -1,29 +1,54 @@
-use crate::{commands, config::Config, sync_error::SyncError};
-use chrono::{DateTime, SecondsFormat, Utc};
-use exec_rs::{CommandExec, Exec};
+use crate::{
+    clock, commands,
+    config::{Config, SyncMode},
+    executor::{self, Executor, ProcessExecutor},
+    filter::FilterRules,
+    history,
+    hooks::{HookContext, HookStage},
+    manifest,
+    metadata::{self, SnapshotMetadata},
+    metrics,
+    naming::NamingScheme,
+    notification,
+    outcome::{RunOutcome, RunStatus},
+    progress::{Phase, Progress, TransferProgress},
+    retention,
+    rsync_stats::RsyncStats,
+    state::JobState,
+    sync_error::SyncError,
+};
+use chrono::{DateTime, Utc};
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-pub struct Sync<T: Exec> {
+pub struct Sync<T: Executor> {
     exec: T,
     config: Config,
+    progress: bool,
 }
 
-impl Sync<CommandExec> {
+impl Sync<ProcessExecutor> {
     pub fn new(config: Config) -> Self {
-        Self {
-            exec: CommandExec {},
-            config,
-        }
+        Self::new_with_exec(config, ProcessExecutor {})
     }
 }
 
-impl<T: Exec> Sync<T> {
+impl<T: Executor> Sync<T> {
     /// constructor
     pub fn new_with_exec(config: Config, exec: T) -> Self {
-        Self { exec, config }
+        Self {
+            exec,
+            config,
+            progress: false,
+        }
+    }
+
+    /// show the phase of the run and the progress of the transfer (see `Progress`)
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
     }
 
     pub fn execute(&self) -> Result<(), SyncError> {
@@ -31,66 +56,423 @@ impl<T: Exec> Sync<T> {
     }
 
     fn execute_with_time(&self, date_time: &DateTime<Utc>) -> Result<(), SyncError> {
+        // create snapshot path
+        let scheme = NamingScheme::from_config(&self.config)?;
+        let snapshot_name = scheme.format(date_time);
+        let snapshot_path = Path::new(&self.config.snapshot).join(&snapshot_name);
+
+        // the transfer of an interrupted run is resumed from the files kept in the partial directory
+        if let Some(start) = self.interrupted_run() {
+            match self.config.rsync.effective_partial_dir() {
+                Some(_) => log::info!("resuming the transfer of the run interrupted at {}", start),
+                None => log::warn!(
+                    "the transfer of the run interrupted at {} cannot be resumed, as no partial directory is used",
+                    start
+                ),
+            }
+        }
+
+        let mut outcome = RunOutcome::new(self.config.job_name(), *date_time);
+        let mut progress = Progress::new(self.progress);
+        let res = self.run(
+            date_time,
+            &scheme,
+            &snapshot_name,
+            &mut outcome,
+            &mut progress,
+        );
+
+        progress.finish();
+
+        outcome.status = match &res {
+            Ok(status) => *status,
+            // the error of the stopped command may also be reported by a hook
+            Err(e) if matches!(e, SyncError::Interrupted) || executor::interrupted() => {
+                log::warn!("backup interrupted; the next run resumes it");
+                RunStatus::Interrupted
+            }
+            Err(e) => {
+                log::error!("backup failed: {}", e);
+                RunStatus::Failure
+            }
+        };
+
+        // also run for interrupted runs, e.g. to restart services stopped by the pre_sync hook
+        if res.is_err() {
+            if let Err(hook_error) =
+                self.run_hook(HookStage::OnFailure, &snapshot_path, outcome.status.name())
+            {
+                log::error!("{}", hook_error);
+            }
+        }
+        outcome.end = Utc::now();
+        outcome.error = res.as_ref().err().map(|e| e.to_string());
+        self.report(&outcome);
+
+        res.map(|_| ())
+    }
+
+    /// start of the run to be resumed (if the last run was interrupted before creating its snapshot)
+    fn interrupted_run(&self) -> Option<DateTime<Utc>> {
+        let state_file = self.config.state_file()?;
+
+        match JobState::read(&state_file) {
+            Ok(state) => state.interrupted,
+            Err(e) => {
+                log::warn!("could not read state: {}", e);
+                None
+            }
+        }
+    }
+
+    /// send notifications, update the job state and history and write the metrics; errors are logged, but do not fail the run
+    fn report(&self, outcome: &RunOutcome) {
+        let state_file = self.config.state_file();
+        let mut state = match &state_file {
+            Some(path) => JobState::read(path).unwrap_or_else(|e| {
+                log::warn!("could not read state: {}", e);
+                JobState::default()
+            }),
+            None => JobState::default(),
+        };
+        let previous = state.last_outcome.as_ref().map(|o| o.status);
+
+        for notification in &self.config.notifications {
+            if notification.when.applies(outcome.status, previous) {
+                if let Err(e) = notification::send(&self.exec, &notification.sink, outcome) {
+                    log::error!("could not send notification: {}", e);
+                }
+            }
+        }
+
+        state.update(outcome);
+
+        if let Some(path) = &state_file {
+            if let Err(e) = state.write(path) {
+                log::error!("could not write state: {}", e);
+            }
+        }
+
+        if let Some(path) = self.config.history_file() {
+            if let Err(e) = history::append(&path, outcome) {
+                log::error!("could not write history: {}", e);
+            }
+        }
+
+        if let Some(path) = &self.config.metrics_file {
+            if let Err(e) = metrics::write(
+                Path::new(path),
+                outcome,
+                &self.config.snapshot_suffix,
+                state.last_success,
+            ) {
+                log::error!("could not write metrics: {}", e);
+            }
+        }
+    }
+
+    fn run(
+        &self,
+        date_time: &DateTime<Utc>,
+        scheme: &NamingScheme,
+        snapshot_name: &str,
+        outcome: &mut RunOutcome,
+        progress: &mut Progress,
+    ) -> Result<RunStatus, SyncError> {
+        let snapshot_path = &Path::new(&self.config.snapshot).join(snapshot_name);
+
+        self.config.source.validate()?;
+
+        // in pull mode, the source is on the client and the snapshot dates are taken from the clock of the server
+        let push = self.config.mode == SyncMode::Push;
+
+        if push && self.config.rsync.one_file_system {
+            self.config.source.warn_nested_mounts();
+        }
+        if let Some(max_skew) = self.config.max_clock_skew.as_ref().filter(|_| push) {
+            log::debug!("checking clock skew");
+            clock::check_clock_skew(
+                &self.exec,
+                &self.config.ssh_credentials,
+                max_skew.try_into()?,
+                self.config.on_clock_skew,
+            )?;
+        }
+        self.run_hook(HookStage::PreSync, snapshot_path, "running")?;
         // sync backup
         log::debug!("syncing backup");
-        commands::sync_backup(
-            &self.exec,
-            &self.config.ssh_credentials,
-            Path::new(&self.config.exclude_file),
-            Path::new(&self.config.source),
-            Path::new(&self.config.destination),
-            Path::new(&self.config.log_file),
-        )?;
-        // create snapshot path
-        let mut snapshot_path = Path::new(&self.config.snapshot).to_path_buf();
+        progress.phase(Phase::Sync);
+        executor::check_interrupted()?;
+        let (output, exit_status) = match self.transfer(progress)? {
+            Some(transfer) => transfer,
+            None => return self.stop_at_deadline(snapshot_path),
+        };
 
-        snapshot_path.push(format!(
-            "{}_{}",
-            date_time.to_rfc3339_opts(SecondsFormat::Secs, true),
-            self.config.snapshot_suffix
-        ));
+        outcome.stats = Some(RsyncStats::parse(&output));
+        self.run_hook(HookStage::PostSync, snapshot_path, "running")?;
+        self.run_hook(HookStage::PreSnapshot, snapshot_path, "running")?;
         // create snapshot
-        commands::create_snapshot(
+        progress.phase(Phase::Snapshot);
+        executor::check_interrupted()?;
+        if let Some(parent) = snapshot_path
+            .parent()
+            .filter(|_| snapshot_name.contains('/'))
+        {
+            commands::server_exec(
+                &self.exec,
+                self.config.server(),
+                &[
+                    "mkdir",
+                    "-p",
+                    parent
+                        .to_str()
+                        .ok_or_else(|| SyncError::PathConversionError("snapshot".to_string()))?,
+                ],
+            )?;
+        }
+        if let Err(e) = commands::create_snapshot(
             &self.exec,
-            &self.config.ssh_credentials,
+            self.config.server(),
             Path::new(&self.config.destination),
-            &snapshot_path,
+            snapshot_path,
+        ) {
+            // an interrupted or failed copy leaves the incomplete copy behind; the snapshot itself is never touched
+            if !matches!(e, SyncError::SnapshotExists(_)) {
+                self.remove_incomplete_snapshot(&commands::incomplete_snapshot_path(snapshot_path));
+            }
+            return Err(e);
+        }
+        outcome.snapshot = Some(snapshot_path.to_string_lossy().to_string());
+        // store the metadata of the run in the snapshot
+        log::debug!("writing snapshot metadata");
+        metadata::write_metadata(
+            &self.exec,
+            self.config.server(),
+            snapshot_path,
+            &SnapshotMetadata {
+                name: snapshot_name.to_string(),
+                job: self.config.job_name().to_string(),
+                source_host: self.config.source_host(),
+                tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                start: *date_time,
+                end: Utc::now(),
+                exit_status,
+                stats: outcome.stats.clone(),
+                exclude_file_sha256: match &self.config.exclude_file {
+                    Some(exclude_file) => {
+                        Some(commands::file_sha256(&self.exec, Path::new(exclude_file))?)
+                    }
+                    None => None,
+                },
+            },
         )?;
         // get all snapshots
+        progress.phase(Phase::List);
+        executor::check_interrupted()?;
         let snapshots = commands::get_snapshots(
             &self.exec,
-            &self.config.ssh_credentials,
+            self.config.server(),
             Path::new(&self.config.snapshot),
+            scheme,
         )?;
-        // find snapshots to be deleted
-        let to_be_deleted = policer::police(
-            date_time,
-            &self
-                .config
-                .policy
+        let snapshot_paths = snapshots
+            .iter()
+            .map(|(_, name)| Path::new(&self.config.snapshot).join(name))
+            .collect::<Vec<PathBuf>>();
+        let snapshot_metadata = metadata::read_metadata(
+            &self.exec,
+            self.config.server(),
+            &snapshot_paths
                 .iter()
-                .map(|e| e.try_into())
-                .collect::<Result<Vec<chrono::Duration>, SyncError>>()?[..],
-            &snapshots[..],
-        );
+                .map(|p| p.as_path())
+                .collect::<Vec<&Path>>(),
+        )?;
+        // create the manifest, reusing the hashes of the previous snapshot
+        if self.config.manifest {
+            let previous = snapshots
+                .iter()
+                .filter(|(_, name)| name != snapshot_name)
+                .max_by_key(|(date, _)| *date)
+                .map(|(_, name)| Path::new(&self.config.snapshot).join(name));
+
+            log::debug!("creating manifest");
+            progress.phase(Phase::Manifest);
+            executor::check_interrupted()?;
+            let (_, manifest_sha256) = manifest::create_manifest(
+                &self.exec,
+                self.config.server(),
+                snapshot_path,
+                previous.as_deref(),
+            )?;
+
+            self.record_manifest_hash(snapshot_name, manifest_sha256);
+        }
+        // find snapshots to be deleted
+        progress.phase(Phase::Prune);
+        let to_be_deleted =
+            retention::snapshots_to_prune(&self.config, date_time, &snapshots, &snapshot_metadata)?;
         // remove snapshots
-        for (_, delete) in to_be_deleted {
+        for delete in &to_be_deleted {
             let mut delete_path = PathBuf::from_str(&self.config.snapshot)?;
 
-            delete_path.push(&delete);
+            delete_path.push(delete);
+
+            executor::check_interrupted()?;
+            commands::delete_snapshot(&self.exec, self.config.server(), &delete_path)?;
+            outcome.pruned += 1;
+        }
+        if self.config.manifest {
+            self.forget_manifest_hashes(&to_be_deleted);
+        }
+
+        let remaining = snapshots
+            .iter()
+            .filter(|(_, name)| !to_be_deleted.contains(name))
+            .map(|(date, _)| *date)
+            .collect::<Vec<DateTime<Utc>>>();
+
+        outcome.snapshot_count = Some(remaining.len());
+        outcome.oldest_snapshot = remaining.iter().min().copied();
+        outcome.newest_snapshot = remaining.iter().max().copied();
 
-            commands::delete_snapshot(&self.exec, &self.config.ssh_credentials, &delete_path)?;
+        self.run_hook(HookStage::PostPrune, snapshot_path, "success")?;
+
+        Ok(RunStatus::Success)
+    }
+
+    /// record the hash of the manifest of a new snapshot outside the snapshot (see `ManifestHashes`); errors are logged, but do not fail the run
+    fn record_manifest_hash(&self, snapshot_name: &str, manifest_sha256: String) {
+        let Some(path) = self.config.manifest_hashes_file() else {
+            log::warn!("no state directory configured; the hash of the manifest is not recorded");
+            return;
+        };
+        let res = manifest::ManifestHashes::read(&path).and_then(|mut hashes| {
+            hashes.0.insert(snapshot_name.to_string(), manifest_sha256);
+            hashes.write(&path)
+        });
+
+        if let Err(e) = res {
+            log::error!("could not record the hash of the manifest: {}", e);
         }
+    }
+
+    /// remove the hashes of the manifests of pruned snapshots; errors are logged, but do not fail the run
+    fn forget_manifest_hashes(&self, pruned: &[String]) {
+        let Some(path) = self
+            .config
+            .manifest_hashes_file()
+            .filter(|_| !pruned.is_empty())
+        else {
+            return;
+        };
+        let res = manifest::ManifestHashes::read(&path).and_then(|mut hashes| {
+            hashes.0.retain(|name, _| !pruned.contains(name));
+            hashes.write(&path)
+        });
 
-        Ok(())
+        if let Err(e) = res {
+            log::error!("could not remove the hashes of the pruned manifests: {}", e);
+        }
+    }
+
+    /// remove the incomplete copy left behind by a failed snapshot creation (see `commands::create_snapshot`); errors are logged (the copy may have failed before creating it)
+    fn remove_incomplete_snapshot(&self, incomplete_path: &Path) {
+        log::warn!(
+            "removing the incomplete snapshot {}",
+            incomplete_path.to_string_lossy()
+        );
+        if let Err(e) = commands::delete_snapshot(&self.exec, self.config.server(), incomplete_path)
+        {
+            log::warn!("could not remove the incomplete snapshot: {}", e);
+        }
+    }
+
+    /// end a run whose transfer was stopped at the deadline; the post_sync hook is still run, e.g. to restart services stopped by the pre_sync hook
+    fn stop_at_deadline(&self, snapshot_path: &Path) -> Result<RunStatus, SyncError> {
+        log::warn!("deadline reached; the transfer was stopped without creating a snapshot");
+        self.run_hook(
+            HookStage::PostSync,
+            snapshot_path,
+            RunStatus::Partial.name(),
+        )?;
+
+        Ok(RunStatus::Partial)
+    }
+
+    /// run rsync with the bandwidth limit of the current window; returns the output and exit status of rsync or `None` if the transfer was stopped at the deadline
+    fn transfer(&self, progress: &mut Progress) -> Result<Option<(String, i32)>, SyncError> {
+        loop {
+            let start = Utc::now();
+            let limits = self
+                .config
+                .bandwidth
+                .limits(&self.config.time_zone.convert(&start).naive_local());
+            let show_progress = progress.enabled();
+            let mut on_progress = |p: &TransferProgress| progress.transfer(p);
+            let res = commands::sync_backup(
+                &self.exec,
+                &self.config.ssh_credentials,
+                self.config.mode,
+                &self.config.rsync,
+                &FilterRules::from_config(&self.config),
+                &self.config.source,
+                Path::new(&self.config.destination),
+                Path::new(&self.config.log_file),
+                &limits,
+                show_progress.then_some(&mut on_progress as &mut dyn FnMut(&TransferProgress)),
+            );
+
+            match res? {
+                // rsync exits with a dedicated status when it reaches the time limit
+                (_, commands::RSYNC_TIME_LIMIT) => {
+                    if limits.deadline {
+                        return Ok(None);
+                    }
+
+                    log::info!("bandwidth window ended; restarting the transfer");
+                }
+                transfer => return Ok(Some(transfer)),
+            }
+        }
+    }
+
+    /// run the hook configured for the stage (if any)
+    fn run_hook(
+        &self,
+        stage: HookStage,
+        snapshot_path: &Path,
+        status: &str,
+    ) -> Result<(), SyncError> {
+        match self.config.hooks.get(stage) {
+            Some(hook) => {
+                log::debug!("running hook \"{}\"", stage.name());
+                commands::run_hook(
+                    &self.exec,
+                    self.config.client(),
+                    stage,
+                    hook,
+                    &HookContext {
+                        job: self.config.job_name(),
+                        snapshot: &snapshot_path.to_string_lossy(),
+                        status,
+                    },
+                )
+            }
+            None => Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{custom_duration::CustomDuration, ssh_credentials::SshCredentials};
-    use chrono::SecondsFormat;
+    use crate::{
+        custom_duration::CustomDuration,
+        hooks::{Hook, HookFailurePolicy, Hooks},
+        notification::{Notification, NotificationSink, NotifyWhen},
+        ssh_credentials::SshCredentials,
+    };
+    use chrono::{SecondsFormat, TimeZone};
     use mockall::Sequence;
 
     #[test]
@@ -102,6 +484,7 @@ mod test {
             "snapshot/{}_test_user",
             <DateTime<Utc>>::from(date_time).to_rfc3339_opts(SecondsFormat::Secs, true)
         );
+        let metadata_path = snapshot.clone();
 
         mock.expect_exec()
             .times(1)
@@ -117,10 +500,11 @@ mod test {
                         "--exclude-from=exclude_file",
                         "--delete-after",
                         "--delete-excluded",
+                        "--partial-dir=.rsync-partial",
+                        "--stats",
+                        "--log-file=log_file",
                         "source",
                         "user@host:destination",
-                        ">",
-                        "log_file",
                     ]
                 );
                 Ok(String::new())
@@ -139,10 +523,13 @@ mod test {
                         "-i",
                         "id_file",
                         "host",
-                        "cp",
-                        "-al",
-                        "destination",
-                        &snapshot
+                        "sh",
+                        "-c",
+                        &format!(
+                            "'if [ -e {0} ]; then echo exists; else rm -rf {1} && cp -al destination {1} && mv -T {1} {0}; fi'",
+                            snapshot,
+                            format!("{}.incomplete", snapshot.replace("snapshot/", "snapshot/."))
+                        )
                     ]
                 );
 
@@ -150,6 +537,27 @@ mod test {
             })
             .in_sequence(&mut seq);
 
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "sha256sum");
+                assert_eq!(args, &["exclude_file"]);
+                Ok(String::from("e3b0c442  exclude_file\n"))
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(move |command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(args[5..7], ["sh", "-c"]);
+                assert!(args[7].contains(&format!("{}/.backup-rsync/metadata.json", metadata_path)));
+                assert!(args[7].contains(r#""job":"test_user""#));
+                assert!(args[7].contains(r#""exclude_file_sha256":"e3b0c442""#));
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
         mock.expect_exec()
             .times(1)
             .returning(|command, args| {
@@ -165,6 +573,16 @@ mod test {
             })
             .in_sequence(&mut seq);
 
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(args[5..7], ["sh", "-c"]);
+                assert!(args[7].contains("for d in snapshot/2022-11-01T12:00:00Z_test_user"));
+                Ok(String::from("\n\n\n\n"))
+            })
+            .in_sequence(&mut seq);
+
         mock.expect_exec()
             .times(1)
             .returning(|command, args| {
@@ -188,22 +606,426 @@ mod test {
             .in_sequence(&mut seq);
 
         let config = Config {
-            source: "source".to_string(),
+            source: "source".into(),
             destination: "destination".to_string(),
-            exclude_file: "exclude_file".to_string(),
+            exclude_file: Some("exclude_file".to_string()),
             log_file: "log_file".to_string(),
-            ssh_credentials: SshCredentials {
-                host: "host".to_string(),
-                id_file: "id_file".to_string(),
-                user: "user".to_string(),
-            },
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
             snapshot: "snapshot".to_string(),
             snapshot_suffix: "test_user".to_string(),
             policy: vec![CustomDuration::minutes(30), CustomDuration::days(2)],
+            ..Default::default()
         };
         let sync = Sync::new_with_exec(config, mock);
 
         sync.execute_with_time(&date_time.into())
             .expect("failed to execute");
     }
+
+    #[test]
+    fn execute_with_hooks() {
+        let mut seq = Sequence::new();
+        let mut mock = exec_rs::MockExec::new();
+        let date_time = Utc::now();
+        let snapshot = format!(
+            "snapshot/{}_test_user",
+            date_time.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        let pre_sync_env = format!("BACKUP_RSYNC_SNAPSHOT={}", snapshot);
+        let post_prune_env = pre_sync_env.clone();
+
+        mock.expect_exec()
+            .times(1)
+            .returning(move |command, args| {
+                assert_eq!(command, "env");
+                assert_eq!(
+                    args,
+                    &[
+                        "BACKUP_RSYNC_HOOK=pre_sync",
+                        "BACKUP_RSYNC_JOB=job",
+                        &pre_sync_env,
+                        "BACKUP_RSYNC_STATUS=running",
+                        "sh",
+                        "-c",
+                        "systemctl stop db",
+                    ]
+                );
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, _| {
+                assert_eq!(command, "rsync");
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert!(args[7].contains("cp -al"));
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, _| {
+                assert_eq!(command, "sha256sum");
+                Ok(String::from("e3b0c442  exclude_file\n"))
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(args[5], "sh");
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(args[5], "ls");
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(move |command, args| {
+                assert_eq!(command, "env");
+                assert_eq!(
+                    args,
+                    &[
+                        "BACKUP_RSYNC_HOOK=post_prune",
+                        "BACKUP_RSYNC_JOB=job",
+                        &post_prune_env,
+                        "BACKUP_RSYNC_STATUS=success",
+                        "sh",
+                        "-c",
+                        "systemctl start db",
+                    ]
+                );
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        let config = Config {
+            name: Some("job".to_string()),
+            source: "source".into(),
+            destination: "destination".to_string(),
+            exclude_file: Some("exclude_file".to_string()),
+            log_file: "log_file".to_string(),
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test_user".to_string(),
+            policy: vec![CustomDuration::days(2)],
+            hooks: Hooks {
+                pre_sync: Some(Hook {
+                    command: "systemctl stop db".to_string(),
+                    timeout: None,
+                    on_error: HookFailurePolicy::Abort,
+                }),
+                post_prune: Some(Hook {
+                    command: "systemctl start db".to_string(),
+                    timeout: None,
+                    on_error: HookFailurePolicy::Continue,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(config, mock);
+
+        sync.execute_with_time(&date_time)
+            .expect("failed to execute");
+    }
+
+    #[test]
+    fn execute_with_notification() {
+        let mut seq = Sequence::new();
+        let mut mock = exec_rs::MockExec::new();
+        let date_time = Utc::now();
+        let state_dir =
+            std::env::temp_dir().join(format!("backup-rsync-sync-state-{}", std::process::id()));
+
+        // no exclude file is configured, so no hash is computed for the metadata
+        for command in ["rsync", "ssh", "ssh", "ssh"] {
+            mock.expect_exec()
+                .times(1)
+                .returning(move |c, _| {
+                    assert_eq!(c, command);
+                    Ok(String::new())
+                })
+                .in_sequence(&mut seq);
+        }
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "env");
+                assert_eq!(args[1], "BACKUP_RSYNC_STATUS=success");
+                assert_eq!(args[5..], ["sh", "-c", "notify"]);
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        let config = Config {
+            source: "source".into(),
+            destination: "destination".to_string(),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test_user".to_string(),
+            notifications: vec![Notification {
+                when: NotifyWhen::OnStateChange,
+                sink: NotificationSink::Command {
+                    command: "notify".to_string(),
+                },
+            }],
+            state_dir: Some(state_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(config, mock);
+
+        sync.execute_with_time(&date_time)
+            .expect("failed to execute");
+
+        let state = JobState::read(&state_dir.join("test_user.json")).unwrap();
+
+        assert_eq!(
+            state.last_outcome.map(|o| o.status),
+            Some(RunStatus::Success)
+        );
+        std::fs::remove_dir_all(&state_dir).unwrap();
+    }
+
+    #[test]
+    fn execute_resume() {
+        let mut seq = Sequence::new();
+        let mut mock = exec_rs::MockExec::new();
+        let state_dir =
+            std::env::temp_dir().join(format!("backup-rsync-sync-resume-{}", std::process::id()));
+        let state_file = state_dir.join("test_user.json");
+        let interrupted = Utc.ymd(2022, 11, 1).and_hms(12, 0, 0);
+
+        JobState {
+            interrupted: Some(interrupted),
+            ..Default::default()
+        }
+        .write(&state_file)
+        .unwrap();
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "rsync");
+                assert!(args.contains(&"--partial-dir=.rsync-partial"));
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        mock.expect_exec()
+            .times(1)
+            .returning(|command, args| {
+                assert_eq!(command, "ssh");
+                assert_eq!(
+                    args[5..],
+                    [
+                        "sh",
+                        "-c",
+                        "'if [ -e snapshot/2022-11-02T12:00:00Z_test_user ]; then echo exists; else rm -rf snapshot/.2022-11-02T12:00:00Z_test_user.incomplete && cp -al destination snapshot/.2022-11-02T12:00:00Z_test_user.incomplete && mv -T snapshot/.2022-11-02T12:00:00Z_test_user.incomplete snapshot/2022-11-02T12:00:00Z_test_user; fi'"
+                    ]
+                );
+                Ok(String::new())
+            })
+            .in_sequence(&mut seq);
+
+        for command in ["ssh", "ssh"] {
+            mock.expect_exec()
+                .times(1)
+                .returning(move |c, _| {
+                    assert_eq!(c, command);
+                    Ok(String::new())
+                })
+                .in_sequence(&mut seq);
+        }
+
+        let config = Config {
+            source: "source".into(),
+            destination: "destination".to_string(),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test_user".to_string(),
+            state_dir: Some(state_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(config, mock);
+
+        sync.execute_with_time(&Utc.ymd(2022, 11, 2).and_hms(12, 0, 0))
+            .expect("failed to execute");
+
+        let state = JobState::read(&state_file).unwrap();
+
+        assert_eq!(state.interrupted, None);
+        assert_eq!(
+            state.last_outcome.map(|o| o.status),
+            Some(RunStatus::Success)
+        );
+        std::fs::remove_dir_all(&state_dir).unwrap();
+    }
+
+    /// executor simulating an interrupt during the commands containing `at`; records the commands
+    struct Interrupted {
+        at: &'static str,
+        commands: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl Executor for Interrupted {
+        fn exec_with(
+            &self,
+            command: &str,
+            args: &[&str],
+            _options: crate::executor::ExecOptions,
+        ) -> Result<String, SyncError> {
+            let command = format!("{} {}", command, args.join(" "));
+            let interrupted = command.contains(self.at);
+
+            self.commands.borrow_mut().push(command);
+
+            match interrupted {
+                true => Err(SyncError::Interrupted),
+                false => Ok(String::new()),
+            }
+        }
+    }
+
+    #[test]
+    fn execute_interrupted() {
+        let state_dir = std::env::temp_dir().join(format!(
+            "backup-rsync-sync-interrupted-{}",
+            std::process::id()
+        ));
+        let config = Config {
+            source: "source".into(),
+            destination: "destination".to_string(),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test_user".to_string(),
+            state_dir: Some(state_dir.to_string_lossy().to_string()),
+            hooks: Hooks {
+                pre_sync: Some(Hook {
+                    command: "systemctl stop db".to_string(),
+                    timeout: None,
+                    on_error: HookFailurePolicy::Abort,
+                }),
+                on_failure: Some(Hook {
+                    command: "systemctl start db".to_string(),
+                    timeout: None,
+                    on_error: HookFailurePolicy::Continue,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(
+            config,
+            Interrupted {
+                at: "rsync ",
+                commands: Default::default(),
+            },
+        );
+
+        assert!(matches!(
+            sync.execute_with_time(&Utc::now()),
+            Err(SyncError::Interrupted)
+        ));
+
+        let commands = sync.exec.commands.take();
+        let state = JobState::read(&state_dir.join("test_user.json")).unwrap();
+
+        std::fs::remove_dir_all(&state_dir).unwrap();
+
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0].ends_with("sh -c systemctl stop db"));
+        assert!(commands[1].starts_with("rsync "));
+        assert!(commands[2].contains("BACKUP_RSYNC_STATUS=interrupted"));
+        assert!(commands[2].ends_with("sh -c systemctl start db"));
+        assert_eq!(
+            state.last_outcome.map(|o| o.status),
+            Some(RunStatus::Interrupted)
+        );
+        assert!(state.interrupted.is_some());
+    }
+
+    #[test]
+    fn execute_interrupted_snapshot() {
+        let config = Config {
+            source: "source".into(),
+            destination: "destination".to_string(),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test_user".to_string(),
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(
+            config,
+            Interrupted {
+                at: "cp -al",
+                commands: Default::default(),
+            },
+        );
+
+        assert!(matches!(
+            sync.execute_with_time(&Utc.ymd(2022, 11, 2).and_hms(12, 0, 0)),
+            Err(SyncError::Interrupted)
+        ));
+
+        let commands = sync.exec.commands.take();
+
+        assert_eq!(commands.len(), 3);
+        assert!(commands[1].contains("cp -al"));
+        assert!(commands[2].ends_with("rm -r snapshot/.2022-11-02T12:00:00Z_test_user.incomplete"));
+    }
+
+    #[test]
+    fn stop_at_deadline() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "env");
+            assert_eq!(
+                args[..4],
+                [
+                    "BACKUP_RSYNC_HOOK=post_sync",
+                    "BACKUP_RSYNC_JOB=job",
+                    "BACKUP_RSYNC_SNAPSHOT=snapshot/new",
+                    "BACKUP_RSYNC_STATUS=partial",
+                ]
+            );
+            assert_eq!(args[4..], ["sh", "-c", "systemctl start db"]);
+            Ok(String::new())
+        });
+
+        let config = Config {
+            name: Some("job".to_string()),
+            hooks: Hooks {
+                post_sync: Some(Hook {
+                    command: "systemctl start db".to_string(),
+                    timeout: None,
+                    on_error: HookFailurePolicy::Abort,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sync = Sync::new_with_exec(config, mock);
+
+        assert_eq!(
+            sync.stop_at_deadline(Path::new("snapshot/new")).unwrap(),
+            RunStatus::Partial
+        );
+    }
 }
@@ -14,6 +14,42 @@ pub enum SyncError {
     ChronoParseError(#[from] chrono::ParseError),
     #[error("duration conversion error")]
     DurationConversionError,
+    #[error("hook \"{0}\" failed ({1})")]
+    HookError(String, String),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("invalid snapshot name \"{0}\"")]
+    InvalidSnapshotName(String),
+    #[error("invalid snapshot name template \"{0}\" ({1})")]
+    InvalidTemplate(String, String),
+    #[error("local clock differs from the server clock by {0}s")]
+    ClockSkew(i64),
+    #[error("invalid source ({0})")]
+    InvalidSource(String),
+    #[error("invalid schedule ({0})")]
+    InvalidSchedule(String),
+    #[error("could not resolve \"{0}\" ({1})")]
+    SecretError(String, String),
+    #[error("command \"{0}\" failed ({1})")]
+    CommandFailed(String, String),
+    #[error("command \"{0}\" timed out")]
+    Timeout(String),
+    #[error("interrupted")]
+    Interrupted,
+    #[error("not supported: {0}")]
+    Unsupported(String),
+    #[error("snapshot \"{0}\" already exists")]
+    SnapshotExists(String),
+    #[error("the manifest of \"{0}\" was modified after it was created")]
+    ManifestModified(String),
+    #[error("no snapshot found")]
+    NoSnapshot,
+    #[error("unexpected output ({0})")]
+    UnexpectedOutput(String),
     #[error(transparent)]
     Infallible(#[from] Infallible),
 }
@@ -0,0 +1,267 @@
+use crate::{
+    clock::SnapshotTimeZone,
+    config::{Config, SyncMode},
+    schedule::{MissedRuns, Trigger},
+    sync_error::SyncError,
+};
+use chrono::Duration;
+use std::{convert::TryFrom, path::Path};
+
+/// delay of the first run after boot for jobs scheduled by interval
+const BOOT_DELAY: &str = "5min";
+
+/// service and timer running a job
+#[derive(Debug, PartialEq, Eq)]
+pub struct Units {
+    /// name of the units without the extension (e.g. "backup-rsync-laptop")
+    pub name: String,
+    pub service: String,
+    pub timer: String,
+}
+
+/// generate the systemd units for the job; user units run in the service manager of the user and cannot use most sandboxing options
+pub fn units(
+    config: &Config,
+    config_path: &Path,
+    executable: &Path,
+    user: bool,
+) -> Result<Units, SyncError> {
+    let name = format!("backup-rsync-{}", unit_name(config.job_name()));
+    let schedule = config.schedule.as_ref();
+    let mut service = vec![
+        "[Unit]".to_string(),
+        format!("Description=backup-rsync job {}", config.job_name()),
+        "Wants=network-online.target".to_string(),
+        "After=network-online.target".to_string(),
+    ];
+
+    if matches!(schedule, Some(s) if s.skip_on_battery) {
+        service.push("ConditionACPower=true".to_string());
+    }
+    if matches!(schedule, Some(s) if s.skip_on_metered) {
+        service.push(
+            "# skip_on_metered is not supported by systemd; use the daemon instead".to_string(),
+        );
+    }
+
+    service.extend([
+        String::new(),
+        "[Service]".to_string(),
+        "Type=oneshot".to_string(),
+        format!(
+            "Environment=\"BACK_UP_RSYNC_CONFIG={}\"",
+            escape(path_str(config_path, "configuration")?)
+        ),
+        // variables are expanded in the command line, but not in the environment
+        format!(
+            "ExecStart=\"{}\" run",
+            escape(path_str(executable, "executable")?).replace('$', "$$")
+        ),
+        "Nice=10".to_string(),
+        "IOSchedulingClass=idle".to_string(),
+        "NoNewPrivileges=true".to_string(),
+        "PrivateTmp=true".to_string(),
+    ]);
+
+    if !user {
+        // the source is only read; the paths written by the job are made writable (ignored if missing)
+        let mut writable = vec![Path::new(&config.log_file)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())];
+
+        writable.push(config.state_dir.clone());
+        writable.push(config.metrics_file.as_ref().and_then(|f| {
+            Path::new(f)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+        }));
+
+        if config.mode == SyncMode::Pull {
+            writable.push(Some(config.destination.clone()));
+            writable.push(Some(config.snapshot.clone()));
+        }
+
+        service.extend([
+            "ProtectSystem=strict".to_string(),
+            "ProtectHome=read-only".to_string(),
+            "ProtectKernelTunables=true".to_string(),
+            "ProtectKernelModules=true".to_string(),
+            "ProtectControlGroups=true".to_string(),
+            "RestrictSUIDSGID=true".to_string(),
+            "LockPersonality=true".to_string(),
+        ]);
+        service.extend(
+            writable
+                .into_iter()
+                .flatten()
+                .filter(|p| !p.is_empty())
+                .map(|p| format!("ReadWritePaths=-{}", p)),
+        );
+    }
+
+    let mut timer = vec![
+        "[Unit]".to_string(),
+        format!(
+            "Description=Schedule of backup-rsync job {}",
+            config.job_name()
+        ),
+        String::new(),
+        "[Timer]".to_string(),
+    ];
+
+    match schedule {
+        Some(schedule) => match &schedule.trigger {
+            Trigger::Cron(cron) => {
+                let time_zone = match config.time_zone {
+                    SnapshotTimeZone::Utc => " UTC",
+                    SnapshotTimeZone::Local => "",
+                    SnapshotTimeZone::Fixed(_) => {
+                        return Err(SyncError::Unsupported(
+                            "fixed time zone offsets in systemd timers".to_string(),
+                        ))
+                    }
+                };
+
+                timer.extend(
+                    cron.calendar_events()
+                        .into_iter()
+                        .map(|e| format!("OnCalendar={}{}", e, time_zone)),
+                );
+                timer.push(format!(
+                    "Persistent={}",
+                    schedule.missed == MissedRuns::CatchUp
+                ));
+            }
+            Trigger::Interval(interval) => {
+                timer.push(format!("OnBootSec={}", BOOT_DELAY));
+                timer.push(format!(
+                    "OnUnitActiveSec={}s",
+                    Duration::try_from(interval)?.num_seconds()
+                ));
+            }
+        },
+        None => {
+            timer.push("OnCalendar=daily".to_string());
+            timer.push("Persistent=true".to_string());
+        }
+    }
+
+    timer.extend([
+        String::new(),
+        "[Install]".to_string(),
+        "WantedBy=timers.target".to_string(),
+    ]);
+
+    Ok(Units {
+        name,
+        service: service.join("\n") + "\n",
+        timer: timer.join("\n") + "\n",
+    })
+}
+
+/// replace characters not allowed in unit names
+fn unit_name(job: &str) -> String {
+    job.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "-_.".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+fn path_str<'a>(path: &'a Path, name: &str) -> Result<&'a str, SyncError> {
+    path.to_str()
+        .ok_or_else(|| SyncError::PathConversionError(name.to_string()))
+}
+
+/// escape a value for a quoted setting of a unit (specifiers start with `%`)
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(schedule: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "name": "home dir",
+                "source": "/home",
+                "destination": "backup",
+                "log_file": "/var/log/backup-rsync/home.log",
+                "ssh_credentials": {{"user": "user", "id_file": "id_file", "host": "host"}},
+                "snapshot": "snapshots",
+                "snapshot_suffix": "home",
+                "policy": [],
+                "state_dir": "/var/lib/backup-rsync",
+                "schedule": {}
+            }}"#,
+            schedule
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn units() {
+        let units = super::units(
+            &config(r#"{"cron": "30 2 * * *", "skip_on_battery": true}"#),
+            Path::new("/etc/backup-rsync/home.json"),
+            Path::new("/usr/bin/backup-rsync"),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(units.name, "backup-rsync-home_dir");
+        assert!(units.service.contains("ConditionACPower=true\n"));
+        assert!(units
+            .service
+            .contains("Environment=\"BACK_UP_RSYNC_CONFIG=/etc/backup-rsync/home.json\"\n"));
+        assert!(units
+            .service
+            .contains("ExecStart=\"/usr/bin/backup-rsync\" run\n"));
+        assert!(units.service.contains(
+            "ReadWritePaths=-/var/log/backup-rsync\nReadWritePaths=-/var/lib/backup-rsync\n"
+        ));
+        assert!(units
+            .timer
+            .contains("OnCalendar=*-*-* 02:30:00 UTC\nPersistent=true\n"));
+    }
+
+    #[test]
+    fn escaped_paths() {
+        let units = super::units(
+            &config(r#"{"interval": {"hours": 6}}"#),
+            Path::new(r#"/etc/backup-rsync/100%"home".json"#),
+            Path::new(r"/opt/$bin\backup-rsync"),
+            false,
+        )
+        .unwrap();
+
+        assert!(units.service.contains(
+            r#"Environment="BACK_UP_RSYNC_CONFIG=/etc/backup-rsync/100%%\"home\".json""#
+        ));
+        assert!(units
+            .service
+            .contains(r#"ExecStart="/opt/$$bin\\backup-rsync" run"#));
+    }
+
+    #[test]
+    fn user_units() {
+        let units = super::units(
+            &config(r#"{"interval": {"hours": 6}, "missed": "skip"}"#),
+            Path::new("/home/user/.config/backup-rsync.json"),
+            Path::new("/usr/bin/backup-rsync"),
+            true,
+        )
+        .unwrap();
+
+        assert!(!units.service.contains("ProtectSystem"));
+        assert!(units
+            .timer
+            .contains("OnBootSec=5min\nOnUnitActiveSec=21600s\n"));
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{
+    commands,
+    config::{Config, SyncMode},
+    executor::Executor,
+    file_util::TempFile,
+    filter::FilterRules,
+    manifest,
+    naming::NamingScheme,
+    source::Source,
+    sync_error::SyncError,
+};
+use serde::Serialize;
+use std::{
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// result of comparing the source with a snapshot using checksums
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub snapshot: String,
+    /// number of sampled files (`None` if the whole tree was compared)
+    pub sampled: Option<usize>,
+    /// files with identical size and modification time, but different content (or failing the manifest check)
+    pub corrupted: Vec<String>,
+    /// files changed in the source since the snapshot was taken
+    pub changed: Vec<String>,
+    /// files in the source, but not in the snapshot
+    pub missing: Vec<String>,
+    /// files in the snapshot, but not in the source
+    pub extra: Vec<String>,
+}
+
+impl VerifyReport {
+    /// parse the output of `rsync --checksum --itemize-changes --dry-run` from the source to the snapshot
+    pub fn parse(snapshot: &str, sampled: Option<usize>, output: &str) -> Self {
+        let mut report = VerifyReport {
+            snapshot: snapshot.to_string(),
+            sampled,
+            ..Default::default()
+        };
+
+        for line in output.lines() {
+            if let Some(path) = line.strip_prefix("*deleting") {
+                report.extra.push(path.trim_start().to_string());
+                continue;
+            }
+
+            let (item, path) = match (line.get(..11), line.get(12..)) {
+                (Some(item), Some(path)) if !path.is_empty() => (item.as_bytes(), path),
+                _ => continue,
+            };
+
+            if !b"<>ch.".contains(&item[0]) {
+                continue;
+            }
+
+            if item[2] == b'+' {
+                report.missing.push(path.to_string());
+            } else if item[1] == b'f' {
+                // columns: checksum, size, modification time
+                match (item[2], item[3], item[4]) {
+                    (b'c', b'.', b'.') => report.corrupted.push(path.to_string()),
+                    (b'c', _, _) | (_, b's', _) | (_, _, b't') => {
+                        report.changed.push(path.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        report
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+
+    pub fn format_text(&self) -> String {
+        let mut res = format!("snapshot: {}\n", self.snapshot);
+
+        if let Some(sampled) = self.sampled {
+            res.push_str(&format!("sampled files: {}\n", sampled));
+        }
+
+        for (title, paths) in [
+            ("corrupted", &self.corrupted),
+            ("changed", &self.changed),
+            ("missing", &self.missing),
+            ("extra", &self.extra),
+        ] {
+            res.push_str(&format!("{}: {}\n", title, paths.len()));
+
+            for path in paths {
+                res.push_str(&format!("  {}\n", path));
+            }
+        }
+
+        res
+    }
+}
+
+/// compare the source with the latest snapshot using checksums; optionally only a random sample of files is compared
+pub fn verify(
+    exec: &dyn Executor,
+    config: &Config,
+    sample: Option<usize>,
+) -> Result<VerifyReport, SyncError> {
+    let snapshot = latest_snapshot(exec, config)?;
+    let snapshot_path = Path::new(&config.snapshot).join(&snapshot);
+
+    match sample {
+        None => {
+            let output = commands::verify_snapshot(
+                exec,
+                &config.ssh_credentials,
+                config.mode,
+                &FilterRules::from_config(config),
+                &config.source,
+                &snapshot_path,
+                None,
+            )?;
+
+            Ok(VerifyReport::parse(&snapshot, None, &output))
+        }
+        Some(_) if config.mode == SyncMode::Pull => Err(SyncError::Unsupported(
+            "sampling the source in pull mode".to_string(),
+        )),
+        Some(sample) => {
+            let (source_dir, target, files) = match &config.source {
+                Source::Single(source) => {
+                    // the files are listed relative to the source directory, which rsync copies into the destination unless the source ends with "/"
+                    let mut source_dir = source.clone();
+                    let mut target = snapshot_path;
+
+                    if !source_dir.ends_with('/') {
+                        if let Some(name) = Path::new(source).file_name() {
+                            target.push(name);
+                        }
+
+                        source_dir.push('/');
+                    }
+
+                    let files = sample_files(Path::new(source), sample, seed())?;
+
+                    (source_dir, target, files)
+                }
+                Source::Multiple(paths) => {
+                    // the full paths are preserved in the snapshot, so the files are listed relative to the root directory
+                    let starts = paths
+                        .iter()
+                        .map(|p| PathBuf::from(p.trim_start_matches('/')))
+                        .collect::<Vec<_>>();
+                    let files = sample_files_below(Path::new("/"), &starts, sample, seed())?;
+
+                    ("/".to_string(), snapshot_path, files)
+                }
+            };
+            // the list is separated by NUL characters (`--from0`), as file names may contain newlines
+            let list = files
+                .iter()
+                .map(|f| f.as_os_str().as_bytes())
+                .collect::<Vec<_>>()
+                .join(&b'\0');
+            let files_from = TempFile::new("backup-rsync-verify", &list)?;
+            let output = commands::verify_snapshot(
+                exec,
+                &config.ssh_credentials,
+                config.mode,
+                &FilterRules::from_config(config),
+                &Source::Single(source_dir),
+                &target,
+                Some(files_from.path()),
+            )?;
+
+            Ok(VerifyReport::parse(&snapshot, Some(files.len()), &output))
+        }
+    }
+}
+
+/// check the files of the latest snapshot against the hashes in its manifest on the server
+pub fn verify_manifest(exec: &dyn Executor, config: &Config) -> Result<VerifyReport, SyncError> {
+    let snapshot = latest_snapshot(exec, config)?;
+    let manifest_sha256 = match config.manifest_hashes_file() {
+        Some(path) => manifest::ManifestHashes::read(&path)?.0.remove(&snapshot),
+        None => None,
+    };
+
+    if manifest_sha256.is_none() {
+        log::warn!(
+            "no hash of the manifest of \"{}\" is recorded in the state directory; the manifest itself is not checked",
+            snapshot
+        );
+    }
+
+    let corrupted = manifest::check_manifest(
+        exec,
+        config.server(),
+        &Path::new(&config.snapshot).join(&snapshot),
+        manifest_sha256.as_deref(),
+    )?;
+
+    Ok(VerifyReport {
+        snapshot,
+        corrupted,
+        ..Default::default()
+    })
+}
+
+fn latest_snapshot(exec: &dyn Executor, config: &Config) -> Result<String, SyncError> {
+    commands::get_snapshots(
+        exec,
+        config.server(),
+        Path::new(&config.snapshot),
+        &NamingScheme::from_config(config)?,
+    )?
+    .into_iter()
+    .max_by_key(|(date, _)| *date)
+    .map(|(_, name)| name)
+    .ok_or(SyncError::NoSnapshot)
+}
+
+/// select a random sample of regular files below the source (paths relative to the source)
+pub fn sample_files(source: &Path, count: usize, seed: u64) -> Result<Vec<PathBuf>, SyncError> {
+    sample_files_below(source, &[PathBuf::new()], count, seed)
+}
+
+/// select a random sample of regular files below the given paths of a root directory (paths relative to the root)
+pub fn sample_files_below(
+    root: &Path,
+    starts: &[PathBuf],
+    count: usize,
+    seed: u64,
+) -> Result<Vec<PathBuf>, SyncError> {
+    let mut rng = XorShift(seed.max(1));
+    let mut sample = Vec::with_capacity(count);
+    let mut seen = 0;
+    let mut dirs = Vec::new();
+    // reservoir sampling, so that the tree is traversed only once
+    let mut add = |path: PathBuf| {
+        seen += 1;
+
+        if sample.len() < count {
+            sample.push(path);
+        } else {
+            let idx = (rng.next() % seen as u64) as usize;
+
+            if idx < count {
+                sample[idx] = path;
+            }
+        }
+    };
+
+    for start in starts {
+        match root.join(start).is_file() {
+            true => add(start.clone()),
+            false => dirs.push(start.clone()),
+        }
+    }
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = dir.join(entry.file_name());
+
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                add(path);
+            }
+        }
+    }
+
+    sample.sort();
+
+    Ok(sample)
+}
+
+fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(1)
+}
+
+/// simple pseudo random number generator; good enough for sampling
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_credentials::SshCredentials;
+
+    #[test]
+    fn parse() {
+        let output = "*deleting   removed.txt
+.d..t...... ./
+>fc........ rotten.bin
+>fcst...... edited.txt
+.f..t...... touched.txt
+>f+++++++++ new.txt
+";
+
+        assert_eq!(
+            VerifyReport::parse("snapshot", None, output),
+            VerifyReport {
+                snapshot: "snapshot".to_string(),
+                sampled: None,
+                corrupted: vec!["rotten.bin".to_string()],
+                changed: vec!["edited.txt".to_string(), "touched.txt".to_string()],
+                missing: vec!["new.txt".to_string()],
+                extra: vec!["removed.txt".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn sample_files() {
+        let dir = std::env::temp_dir().join(format!("backup-rsync-sample-{}", std::process::id()));
+
+        fs::create_dir_all(dir.join("sub")).unwrap();
+
+        for name in ["a", "b", "sub/c", "sub/d", "sub/e"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        let sample = super::sample_files(&dir, 3, 42).unwrap();
+
+        assert_eq!(sample.len(), 3);
+        assert!(sample.iter().all(|f| dir.join(f).is_file()));
+        assert_eq!(super::sample_files(&dir, 10, 42).unwrap().len(), 5);
+
+        let sample =
+            sample_files_below(&dir, &[PathBuf::from("a"), PathBuf::from("sub")], 10, 42).unwrap();
+
+        assert_eq!(
+            sample,
+            ["a", "sub/c", "sub/d", "sub/e"].map(PathBuf::from).to_vec()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify() {
+        let mut mock = exec_rs::MockExec::new();
+
+        mock.expect_exec().once().returning(|_, args| {
+            assert_eq!(args[5..], ["ls", "-A1", "snapshot"]);
+            Ok("2022-11-01T12:00:00Z_test\n2022-11-02T12:00:00Z_test\n".to_string())
+        });
+        mock.expect_exec().once().returning(|command, args| {
+            assert_eq!(command, "rsync");
+            assert_eq!(
+                args,
+                vec![
+                    "-anci",
+                    "-e",
+                    "ssh -l user -i id_file",
+                    "--exclude=/.backup-rsync/",
+                    "--exclude-from=exclude_file",
+                    "--delete",
+                    "source",
+                    "user@host:snapshot/2022-11-02T12:00:00Z_test",
+                ]
+            );
+            Ok(">fc........ source/rotten.bin\n".to_string())
+        });
+
+        let config = Config {
+            source: "source".into(),
+            exclude_file: Some("exclude_file".to_string()),
+            ssh_credentials: SshCredentials::test("user", "id_file", "host"),
+            snapshot: "snapshot".to_string(),
+            snapshot_suffix: "test".to_string(),
+            ..Default::default()
+        };
+        let report = super::verify(&mock, &config, None).unwrap();
+
+        assert!(!report.is_ok());
+        assert_eq!(report.corrupted, vec!["source/rotten.bin".to_string()]);
+    }
+}